@@ -31,12 +31,14 @@ fn main() {
     let output_file =
         File::create(output_path).expect("failed to open output file");
     let mut builder = ar::Builder::new(output_file);
+    // Store long filenames using the BSD encoding, as BSD `ar` does.
+    builder.set_auto_bsd(true);
 
     for index in 2..num_args {
         let input_path = env::args().nth(index).unwrap();
         let input_path = Path::new(&input_path);
-        builder
-            .append_path(input_path)
-            .expect(&format!("failed to add {:?} to archive", input_path));
+        builder.append_path(input_path).unwrap_or_else(|_| {
+            panic!("failed to add {:?} to archive", input_path)
+        });
     }
 }
@@ -40,9 +40,12 @@ fn main() {
                 .expect("Non UTF-8 filename"),
         )
         .to_path_buf();
-        let mut output_file = File::create(&output_path)
-            .expect(&format!("unable to create file {:?}", output_path));
-        io::copy(&mut entry, &mut output_file)
-            .expect(&format!("failed to extract file {:?}", output_path));
+        let mut output_file =
+            File::create(&output_path).unwrap_or_else(|_| {
+                panic!("unable to create file {:?}", output_path)
+            });
+        io::copy(&mut entry, &mut output_file).unwrap_or_else(|_| {
+            panic!("failed to extract file {:?}", output_path)
+        });
     }
 }
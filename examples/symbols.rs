@@ -25,7 +25,7 @@ fn main() {
         File::open(input_path).expect("failed to open input file");
     let mut archive = ar::Archive::new(input_file);
 
-    for symbol in archive.symbols().expect("failed to parse symbols") {
+    for (symbol, _) in archive.symbols().expect("failed to parse symbols") {
         println!("{}", String::from_utf8_lossy(symbol));
     }
 }
@@ -1,14 +1,34 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{
-    self, BufRead, BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom,
+    self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write,
 };
+use std::path::{Component, Path, PathBuf};
+use std::slice;
+use std::str;
 
-use crate::entry::Entry;
-use crate::error::annotate;
-use crate::header::Header;
-use crate::symbols::Symbols;
+use crate::entry::{bytes_to_path, Entry};
+use crate::error::{annotate, member_context, Error as ArError, Result};
+use crate::header::{
+    gnu_table_name, is_bsd_symbol_table_id, is_gnu_symbol_table_id,
+    read_aix_fixed_header, Header, AIX_FIXED_HEADER_LEN, ENTRY_HEADER_LEN,
+};
+use crate::symbols::{SymbolTable, Symbols};
 
 pub(crate) const GLOBAL_HEADER_LEN: usize = 8;
-pub(crate) const GLOBAL_HEADER: &[u8; GLOBAL_HEADER_LEN] = b"!<arch>\n";
+/// The global header that starts a Common, BSD, or (non-thin) GNU archive.
+pub const COMMON_MAGIC: &[u8; GLOBAL_HEADER_LEN] = b"!<arch>\n";
+/// The global header that starts a GNU thin archive.
+pub const THIN_MAGIC: &[u8; GLOBAL_HEADER_LEN] = b"!<thin>\n";
+/// The global header that starts an AIX big archive.
+pub const AIX_BIG_MAGIC: &[u8; GLOBAL_HEADER_LEN] = b"<bigaf>\n";
+
+/// How much data `unpack_with_progress()` writes between progress reports.
+const UNPACK_CHUNK_SIZE: usize = 8 << 10;
+
+/// How much of the archive `resync()` reads at a time while searching.
+const RESYNC_CHUNK_SIZE: usize = 64 << 10;
 
 pub(crate) const BSD_SYMBOL_LOOKUP_TABLE_ID: &[u8] = b"__.SYMDEF";
 pub(crate) const BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID: &[u8] =
@@ -16,6 +36,7 @@ pub(crate) const BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID: &[u8] =
 
 pub(crate) const GNU_NAME_TABLE_ID: &str = "//";
 pub(crate) const GNU_SYMBOL_LOOKUP_TABLE_ID: &[u8] = b"/";
+pub(crate) const GNU_SYMBOL_LOOKUP_TABLE_64_ID: &[u8] = b"/SYM64/";
 
 /// Variants of the Unix archive format.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -26,10 +47,47 @@ pub enum Variant {
     BSD,
     /// Used by GNU `ar` (and Windows); incompatible with common variant.
     GNU,
+    /// The "big" archive format used by IBM AIX, whose headers differ
+    /// entirely from those of the other variants, and whose members are
+    /// linked together by their offsets.  Such archives can be read, but not
+    /// written; their symbol tables aren't read either.
+    AIX,
+}
+
+impl Variant {
+    /// Determines the variant of the archive that `reader` is positioned at
+    /// the start of, by reading its global header and up to two of its
+    /// member headers (skipping over their data).  The reader is then seeked
+    /// back to where it started, even if an error is returned.  As with
+    /// `Archive::variant()`, an archive whose first members use neither BSD
+    /// nor GNU features is reported as `Variant::Common`.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Result<Variant> {
+        let start = reader.stream_position()?;
+        let result = {
+            let mut archive = Archive::new_seekable(reader.by_ref());
+            detect_variant(&mut archive)
+        };
+        reader.seek(SeekFrom::Start(start))?;
+        result.map_err(ArError::from)
+    }
+}
+
+fn detect_variant<R: Read>(archive: &mut Archive<R>) -> io::Result<Variant> {
+    for _ in 0..2 {
+        match archive.next_entry() {
+            Some(entry) => entry.map(|_| ())?,
+            None => break,
+        }
+        if archive.variant() != Variant::Common {
+            break;
+        }
+    }
+    Ok(archive.variant())
 }
 
+#[derive(Clone)]
 pub(crate) struct HeaderAndLocation {
-    header: Header,
+    pub(crate) header: Header,
     header_start: u64,
     data_start: u64,
 }
@@ -43,11 +101,27 @@ pub struct Archive<R: Read> {
     new_entry_start: u64,
     next_entry_index: usize,
     pub(crate) symbol_table_header: Option<HeaderAndLocation>,
-    pub(crate) symbol_table: Option<Vec<(Vec<u8>, u64)>>,
+    pub(crate) symbol_table: Option<SymbolTable>,
+    name_table_header: Option<HeaderAndLocation>,
+    // The special entry that next_entry_including_special() is returning.
+    special: Option<HeaderAndLocation>,
+    // Used to skip unread entry data, if the reader supports seeking.
+    seek: Option<fn(&mut R, SeekFrom) -> io::Result<u64>>,
+    stream_len: Option<u64>, // The length of a seekable stream, once known.
+    unread: u64, // Bytes of the last entry's data that weren't read.
     started: bool, // True if we've read past the global header.
+    headerless: bool, // True if there is no global header to read.
+    aix_last_member: u64, // Offset of the last member, in an AIX archive.
+    thin: bool,  // True if this is a GNU thin archive.
+    validating: bool, // True while validate() is walking the archive.
+    lenient: bool, // True if minor header deviations are tolerated.
+    size_limit: Option<u64>, // The largest entry size allowed, if any.
+    preserve_permissions: bool, // True if unpack() applies entry modes.
+    preserve_mtime: bool, // True if unpack() applies entry mtimes.
     padding: bool, // True if there's a padding byte before the next entry.
+    peeked: bool, // True if peek_header() has read the next entry's header.
     scanned: bool, // True if entry_headers is complete.
-    error: bool,   // True if we have encountered an error.
+    error: bool, // True if we have encountered an error.
 }
 
 impl<R: Read> Archive<R> {
@@ -63,13 +137,48 @@ impl<R: Read> Archive<R> {
             next_entry_index: 0,
             symbol_table_header: None,
             symbol_table: None,
+            name_table_header: None,
+            special: None,
+            seek: None,
+            stream_len: None,
+            unread: 0,
             started: false,
+            headerless: false,
+            aix_last_member: 0,
+            thin: false,
+            validating: false,
+            lenient: false,
+            size_limit: None,
+            preserve_permissions: false,
+            preserve_mtime: false,
             padding: false,
+            peeked: false,
             scanned: false,
             error: false,
         }
     }
 
+    /// Create a new archive reader for an archive that lacks the `!<arch>\n`
+    /// global header, such as one that was truncated at the front or built
+    /// by concatenating members.  The reader must be positioned at the start
+    /// of the first member header; member headers are then read as usual,
+    /// including GNU and BSD extended names.
+    ///
+    /// Without a global header, a thin archive can't be recognized, and the
+    /// variant is detected only from the members themselves, so detection is
+    /// best-effort: for instance, a GNU archive whose name table is missing
+    /// can't be told apart from a Common one.  Member offsets are counted
+    /// from where the reader was positioned, so if this archive is later
+    /// scanned or rewound by seeking, the first member must be at the start
+    /// of the stream.
+    pub fn new_headerless(reader: R) -> Archive<R> {
+        let mut archive = Archive::new(reader);
+        archive.new_entry_start = 0;
+        archive.started = true;
+        archive.headerless = true;
+        archive
+    }
+
     /// Returns which format variant this archive appears to be so far.
     ///
     /// Note that this may not be accurate before the archive has been fully
@@ -80,11 +189,214 @@ impl<R: Read> Archive<R> {
         self.variant
     }
 
+    /// Returns true if this is a GNU thin archive (one starting with
+    /// `!<thin>\n`), whose members refer to external files rather than
+    /// embedding their data.  Like `variant()`, this is only accurate once the
+    /// global header has been read.
+    pub fn is_thin(&self) -> bool {
+        self.thin
+    }
+
+    /// Returns the contents of the GNU archive name table (the `//` member),
+    /// which holds the identifiers too long to fit in their entry headers,
+    /// or `None` if no name table has been read (yet).  Each name in the
+    /// table ends with `/\n`.
+    pub fn long_names(&self) -> Option<&[u8]> {
+        if self.name_table.is_empty() {
+            None
+        } else {
+            Some(&self.name_table)
+        }
+    }
+
+    /// Resolves the raw identifier field of a GNU entry header (such as
+    /// `/34`, or `foo.o/`), with or without its padding spaces, into the
+    /// identifier it stands for, using the name table read so far.  Special
+    /// identifiers (such as `//`) and identifiers that aren't in the GNU
+    /// style are returned unchanged.  Returns an error carrying
+    /// `ar::Error::NameNotInGnuTable` if the field refers to an offset past
+    /// the end of the name table.
+    pub fn resolve_name<'a>(&'a self, raw: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let mut name = raw;
+        while let Some((&b' ', rest)) = name.split_last() {
+            name = rest;
+        }
+        if name == GNU_NAME_TABLE_ID.as_bytes() || is_gnu_symbol_table_id(name)
+        {
+            return Ok(Cow::Borrowed(name));
+        }
+        if let Some(digits) = name.strip_prefix(b"/") {
+            let start = str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| digits.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    let value = String::from_utf8_lossy(raw).into_owned();
+                    let field = "GNU filename index";
+                    Error::from(ArError::InvalidField { field, value })
+                })?;
+            let name = gnu_table_name(&self.name_table, start)?;
+            return Ok(Cow::Borrowed(name));
+        }
+        Ok(Cow::Borrowed(name.strip_suffix(b"/").unwrap_or(name)))
+    }
+
+    /// Sets whether minor deviations in the numeric fields of entry headers
+    /// are tolerated.  This should be called before reading any entries.
+    ///
+    /// By default (in strict mode), each numeric field must consist of
+    /// nothing but digits followed by padding whitespace; as exceptions, a
+    /// timestamp of `-1` is read as zero, as is an empty owner or group ID
+    /// field in a GNU archive.  In lenient mode, leading whitespace and NUL
+    /// bytes in a numeric field are also skipped, and anything following the
+    /// field's leading digits (such as NUL padding or other trailing garbage)
+    /// is ignored; a field must still start with at least one digit, apart
+    /// from the exceptions above, which also allow NUL padding.
+    ///
+    /// An entry with an odd amount of data must be followed by a padding
+    /// byte, which is a newline (or, as some tools write, a NUL byte).  In
+    /// strict mode, any other byte there is an error carrying
+    /// `ar::Error::InvalidPadding`; in lenient mode, the padding byte is
+    /// instead assumed to have been left out, and the byte is taken to be
+    /// the start of the next entry's header.  Neither mode affects other
+    /// checks, such as those for the header terminator.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Sets the largest entry size that is allowed.  Once this is set, an
+    /// entry header (including that of a special entry, such as the GNU
+    /// archive name table) that declares a larger size causes an error
+    /// carrying `ar::Error::SizeLimitExceeded`, rather than the entry being
+    /// returned.  The size checked is the one in the header's file size
+    /// field, which for a BSD entry also counts its extended identifier, and
+    /// it's checked before the identifier or name table is read.  This
+    /// guards consumers of untrusted archives against attempting enormous
+    /// allocations.  By default there is no limit, and passing `None`
+    /// removes one that was set before.
+    pub fn set_size_limit(&mut self, max: Option<u64>) {
+        self.size_limit = max;
+    }
+
     /// Unwrap this archive reader, returning the underlying reader object.
-    pub fn into_inner(self) -> Result<R> {
+    pub fn into_inner(mut self) -> Result<R> {
+        self.skip_unread()?;
         Ok(self.reader)
     }
 
+    /// Moves the reader past any data that the last entry returned didn't
+    /// read, by seeking if possible and otherwise by reading and discarding
+    /// it.
+    fn skip_unread(&mut self) -> io::Result<()> {
+        if self.unread == 0 {
+            return Ok(());
+        }
+        let unread = self.unread;
+        self.unread = 0;
+        match self.seek {
+            Some(seek) => {
+                seek(&mut self.reader, SeekFrom::Current(unread as i64))?;
+            }
+            None => {
+                let mut remaining = self.reader.by_ref().take(unread);
+                let skipped = io::copy(&mut remaining, &mut io::sink())?;
+                if skipped < unread {
+                    return Err(self.truncated_data_error(unread - skipped));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks, when the end of the archive is reached, that skipping over the
+    /// previous entry's data didn't seek past the end of the stream.
+    fn check_not_truncated(&mut self) -> io::Result<()> {
+        let seek = match self.seek {
+            Some(seek) if self.next_entry_index > 0 => seek,
+            _ => return Ok(()),
+        };
+        let position = seek(&mut self.reader, SeekFrom::Current(0))?;
+        let end = seek(&mut self.reader, SeekFrom::End(0))?;
+        if position > end {
+            return Err(self.truncated_data_error(position - end));
+        }
+        Ok(())
+    }
+
+    /// Reads the padding byte that follows an entry with odd-sized data.
+    /// Returns the byte if, in lenient mode, it wasn't a newline or NUL, in
+    /// which case it belongs to the next entry's header.  A padding byte
+    /// missing at the end of the archive is allowed.
+    fn read_padding(&mut self) -> io::Result<Option<u8>> {
+        let mut buffer = [0u8; 1];
+        match self.reader.read_exact(&mut buffer) {
+            Ok(()) if buffer[0] == b'\n' || buffer[0] == 0 => Ok(None),
+            Ok(()) if self.lenient => Ok(Some(buffer[0])),
+            Ok(()) => Err(ArError::InvalidPadding(buffer[0]).into()),
+            Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => {
+                Ok(None)
+            }
+            Err(error) => Err(annotate(error, "failed to read padding byte")),
+        }
+    }
+
+    /// Adds the identifier and offset of the last entry returned to an error
+    /// found while moving past it.
+    fn previous_member_error(&self, error: Error) -> Error {
+        let location = &self.entry_headers[self.next_entry_index - 1];
+        let identifier = Some(location.header.identifier());
+        let offset = Some(location.header_start);
+        member_context(error, "reading", identifier, offset)
+    }
+
+    fn truncated_data_error(&self, missing: u64) -> Error {
+        let location = &self.entry_headers[self.next_entry_index - 1];
+        let expected = self.member_data_size(&location.header);
+        let actual = expected.saturating_sub(missing);
+        ArError::TruncatedData { expected, actual }.into()
+    }
+
+    /// Checks, if the reader is seekable, that an entry's data (of the given
+    /// size, starting at the given offset) doesn't run past the end of the
+    /// stream, so that a truncated archive is caught before the data is read.
+    /// The length of the stream is only looked up once.
+    fn check_data_in_stream(
+        &mut self,
+        data_start: u64,
+        size: u64,
+    ) -> io::Result<()> {
+        let seek = match self.seek {
+            Some(seek) => seek,
+            None => return Ok(()),
+        };
+        let end = match self.stream_len {
+            Some(end) => end,
+            None => {
+                let position = seek(&mut self.reader, SeekFrom::Current(0))?;
+                let end = seek(&mut self.reader, SeekFrom::End(0))?;
+                seek(&mut self.reader, SeekFrom::Start(position))?;
+                self.stream_len = Some(end);
+                end
+            }
+        };
+        if data_start.saturating_add(size) > end {
+            let actual = end.saturating_sub(data_start);
+            return Err(
+                ArError::TruncatedData { expected: size, actual }.into()
+            );
+        }
+        Ok(())
+    }
+
+    fn check_size_limit(&self, header: &Header) -> io::Result<()> {
+        match self.size_limit {
+            Some(limit) if header.size() > limit => {
+                let size = header.size();
+                Err(ArError::SizeLimitExceeded { size, limit }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn is_name_table_id(&self, identifier: &[u8]) -> bool {
         self.variant == Variant::GNU
             && identifier == GNU_NAME_TABLE_ID.as_bytes()
@@ -92,16 +404,23 @@ impl<R: Read> Archive<R> {
 
     fn is_symbol_lookup_table_id(&self, identifier: &[u8]) -> bool {
         match self.variant {
-            Variant::Common => false,
-            Variant::BSD => {
-                identifier == BSD_SYMBOL_LOOKUP_TABLE_ID
-                    || identifier == BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID
-            }
-            Variant::GNU => identifier == GNU_SYMBOL_LOOKUP_TABLE_ID,
+            Variant::Common | Variant::AIX => false,
+            Variant::BSD => is_bsd_symbol_table_id(identifier),
+            Variant::GNU => is_gnu_symbol_table_id(identifier),
+        }
+    }
+
+    /// Returns the number of data bytes stored in the archive for the given
+    /// (non-special) member.  Members of a thin archive have no data.
+    fn member_data_size(&self, header: &Header) -> u64 {
+        if self.thin {
+            0
+        } else {
+            header.size()
         }
     }
 
-    fn read_global_header_if_necessary(&mut self) -> Result<()> {
+    fn read_global_header_if_necessary(&mut self) -> io::Result<()> {
         if self.started {
             return Ok(());
         }
@@ -113,18 +432,121 @@ impl<R: Read> Archive<R> {
                 return Err(annotate(error, "failed to read global header"));
             }
         }
-        if &buffer != GLOBAL_HEADER {
+        if &buffer == THIN_MAGIC {
+            self.variant = Variant::GNU;
+            self.thin = true;
+        } else if &buffer == AIX_BIG_MAGIC {
+            let (first_member, last_member) =
+                match read_aix_fixed_header(&mut self.reader, self.lenient) {
+                    Ok(offsets) => offsets,
+                    Err(error) => {
+                        self.error = true;
+                        return Err(error);
+                    }
+                };
+            self.variant = Variant::AIX;
+            self.new_entry_start = first_member;
+            self.aix_last_member = last_member;
+        } else if &buffer != COMMON_MAGIC {
             self.error = true;
-            let msg = "Not an archive file (invalid global header)";
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+            return Err(ArError::BadMagic.into());
         }
         self.started = true;
         Ok(())
     }
 
     /// Reads the next entry from the archive, or returns None if there are no
-    /// more.
-    pub fn next_entry(&mut self) -> Option<Result<Entry<R>>> {
+    /// more.  If the archive ends in the middle of an entry header, or before
+    /// all of the previous entry's data, this returns an error carrying
+    /// `ar::Error::TruncatedHeader` or `ar::Error::TruncatedData`.  With a
+    /// seekable reader (see `Archive::new_seekable()`), an entry whose data
+    /// would run past the end of the stream is reported as truncated right
+    /// away, rather than once its data has been read.
+    ///
+    /// Special entries, such as symbol tables and the GNU name table, are
+    /// skipped; `next_entry_including_special()` returns them as well.
+    pub fn next_entry(&mut self) -> Option<Result<Entry<'_, R>>> {
+        if !self.peeked {
+            match self.read_next_header() {
+                Some(Ok(())) => {}
+                Some(Err(error)) => return Some(Err(error.into())),
+                None => return None,
+            }
+        }
+        self.peeked = false;
+        let index = self.next_entry_index;
+        let size = self.member_data_size(&self.entry_headers[index].header);
+        self.next_entry_index += 1;
+        let location = &self.entry_headers[index];
+        Some(Ok(Entry {
+            header: &location.header,
+            member_offset: location.header_start,
+            data_offset: location.data_start,
+            reader: self.reader.by_ref(),
+            length: size,
+            position: 0,
+            stream_data_start: None,
+            thin: self.thin,
+            unread: &mut self.unread,
+        }))
+    }
+
+    /// Moves past the next entry without returning it, skipping its data (by
+    /// seeking, if the reader supports it, and otherwise by reading and
+    /// discarding it).  Returns `Ok(false)` if there are no more entries.
+    /// Together with `peek_header()`, this lets the caller decide from an
+    /// entry's header whether to read it or skip it.
+    pub fn skip_entry(&mut self) -> Result<bool> {
+        if !self.peeked {
+            match self.read_next_header() {
+                Some(Ok(())) => {}
+                Some(Err(error)) => return Err(error.into()),
+                None => return Ok(false),
+            }
+        }
+        self.peeked = false;
+        let index = self.next_entry_index;
+        self.unread = self.member_data_size(&self.entry_headers[index].header);
+        self.next_entry_index += 1;
+        if let Err(error) = self.skip_unread() {
+            self.error = true;
+            return Err(error.into());
+        }
+        Ok(true)
+    }
+
+    /// Reads the header of the next entry, without returning the entry
+    /// itself, or returns `Ok(None)` if there are no more entries.  The next
+    /// call to `next_entry()` then returns the entry with that header, so
+    /// this lets the caller look at an entry's identifier or size before
+    /// deciding whether to read it.  Calling this again before
+    /// `next_entry()` returns the same header.
+    pub fn peek_header(&mut self) -> Result<Option<&Header>> {
+        if !self.peeked {
+            match self.read_next_header() {
+                Some(Ok(())) => self.peeked = true,
+                Some(Err(error)) => return Err(error.into()),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(&self.entry_headers[self.next_entry_index].header))
+    }
+
+    /// Moves past the rest of the previous entry and reads the header of the
+    /// next (non-special) entry, leaving the reader at the start of its data
+    /// and its header at `entry_headers[next_entry_index]`.
+    fn read_next_header(&mut self) -> Option<io::Result<()>> {
+        self.read_next_member(false)
+    }
+
+    /// Like `read_next_header()`, except that if `include_special` is true,
+    /// a special entry is stopped at as well, and its location put in
+    /// `special`.  The reader is then past the special entry's data.
+    fn read_next_member(
+        &mut self,
+        include_special: bool,
+    ) -> Option<io::Result<()>> {
+        self.special = None;
         loop {
             if self.error {
                 return None;
@@ -138,106 +560,670 @@ impl<R: Read> Archive<R> {
                 Ok(()) => {}
                 Err(error) => return Some(Err(error)),
             }
+            if let Err(error) = self.skip_unread() {
+                self.error = true;
+                return Some(Err(self.previous_member_error(error)));
+            }
+            if self.variant == Variant::AIX {
+                return self.read_next_aix_header();
+            }
+            let mut misplaced = None;
             if self.padding {
-                let mut buffer = [0u8; 1];
-                match self.reader.read_exact(&mut buffer) {
-                    Ok(()) => {
-                        if buffer[0] != b'\n' {
-                            self.error = true;
-                            let msg = format!(
-                                "invalid padding byte ({})",
-                                buffer[0]
-                            );
-                            let error =
-                                Error::new(ErrorKind::InvalidData, msg);
-                            return Some(Err(error));
-                        }
-                    }
+                match self.read_padding() {
+                    Ok(byte) => misplaced = byte,
                     Err(error) => {
-                        if error.kind() != ErrorKind::UnexpectedEof {
-                            self.error = true;
-                            let msg = "failed to read padding byte";
-                            return Some(Err(annotate(error, msg)));
-                        }
+                        self.error = true;
+                        return Some(Err(self.previous_member_error(error)));
                     }
                 }
                 self.padding = false;
             }
+            // When reading entries again (after a rewind), the headers have
+            // already been recorded, along with where they are.
+            let is_new = self.next_entry_index == self.entry_headers.len();
+            if is_new && misplaced.is_some() {
+                self.new_entry_start -= 1;
+            }
             let header_start = self.new_entry_start;
+            let misplaced: &[u8] = match misplaced {
+                Some(ref byte) => slice::from_ref(byte),
+                None => &[],
+            };
             match Header::read(
-                &mut self.reader,
+                &mut misplaced.chain(&mut self.reader),
                 &mut self.variant,
                 &mut self.name_table,
+                self.validating,
+                self.lenient,
+                self.size_limit,
             ) {
                 Ok(Some((header, header_len))) => {
-                    let size = header.size();
+                    let is_name_table =
+                        self.is_name_table_id(header.identifier());
+                    let is_symbol_table =
+                        self.is_symbol_lookup_table_id(header.identifier());
+                    let size = if is_name_table || is_symbol_table {
+                        header.size()
+                    } else {
+                        self.member_data_size(&header)
+                    };
                     if size % 2 != 0 {
                         self.padding = true;
                     }
-                    if self.next_entry_index == self.entry_headers.len() {
-                        self.new_entry_start += header_len + size + (size % 2);
+                    if !is_new {
+                        if is_name_table || is_symbol_table {
+                            self.special = if !include_special {
+                                None
+                            } else if is_name_table {
+                                self.name_table_header.clone()
+                            } else {
+                                self.symbol_table_header.clone()
+                            };
+                            if self.special.is_some() {
+                                return Some(Ok(()));
+                            }
+                            continue;
+                        }
+                        return Some(Ok(()));
                     }
-                    if self.is_name_table_id(header.identifier()) {
-                        continue;
+                    let data_start = header_start + header_len;
+                    if let Err(error) =
+                        self.check_data_in_stream(data_start, size)
+                    {
+                        self.error = true;
+                        let identifier = Some(header.identifier());
+                        let offset = Some(header_start);
+                        let error = member_context(
+                            error, "reading", identifier, offset,
+                        );
+                        return Some(Err(error));
                     }
-                    if self.is_symbol_lookup_table_id(header.identifier()) {
-                        self.symbol_table_header = Some(HeaderAndLocation {
-                            header,
-                            header_start,
-                            data_start: header_start + header_len,
-                        });
+                    self.new_entry_start += header_len + size + (size % 2);
+                    let location =
+                        HeaderAndLocation { header, header_start, data_start };
+                    if is_name_table || is_symbol_table {
+                        if include_special {
+                            self.special = Some(location.clone());
+                        }
+                        if is_name_table {
+                            self.name_table_header = Some(location);
+                        } else {
+                            self.symbol_table_header = Some(location);
+                        }
+                        if include_special {
+                            return Some(Ok(()));
+                        }
                         continue;
                     }
-                    if self.next_entry_index == self.entry_headers.len() {
-                        self.entry_headers.push(HeaderAndLocation {
-                            header,
-                            header_start,
-                            data_start: header_start + header_len,
-                        });
-                    }
-                    let header =
-                        &self.entry_headers[self.next_entry_index].header;
-                    self.next_entry_index += 1;
-                    return Some(Ok(Entry {
-                        header,
-                        reader: self.reader.by_ref(),
-                        length: size,
-                        position: 0,
-                    }));
+                    self.entry_headers.push(location);
+                    return Some(Ok(()));
                 }
                 Ok(None) => {
+                    if let Err(error) = self.check_not_truncated() {
+                        self.error = true;
+                        return Some(Err(self.previous_member_error(error)));
+                    }
                     self.scanned = true;
                     return None;
                 }
                 Err(error) => {
                     self.error = true;
+                    let offset = Some(header_start);
+                    let error = member_context(error, "reading", None, offset);
                     return Some(Err(error));
                 }
             }
         }
     }
+
+    /// Like `read_next_header()`, for an AIX big archive, whose members are
+    /// found by following the offsets in their headers.
+    fn read_next_aix_header(&mut self) -> Option<io::Result<()>> {
+        let is_new = self.next_entry_index == self.entry_headers.len();
+        let header_start = if is_new {
+            self.new_entry_start
+        } else {
+            self.entry_headers[self.next_entry_index].header_start
+        };
+        if header_start == 0 {
+            if let Err(error) = self.check_not_truncated() {
+                self.error = true;
+                return Some(Err(self.previous_member_error(error)));
+            }
+            self.scanned = true;
+            return None;
+        }
+        match self.read_aix_header_at(header_start) {
+            Ok(location) => {
+                if is_new {
+                    self.entry_headers.push(location);
+                }
+                Some(Ok(()))
+            }
+            Err(error) => {
+                self.error = true;
+                let offset = Some(header_start);
+                Some(Err(member_context(error, "reading", None, offset)))
+            }
+        }
+    }
+
+    /// Moves to and reads the AIX big archive member header at the given
+    /// offset.  Without seeking, this only works for an offset past the end
+    /// of the last entry's data.
+    fn read_aix_header_at(
+        &mut self,
+        header_start: u64,
+    ) -> io::Result<HeaderAndLocation> {
+        match self.seek {
+            Some(seek) => {
+                seek(&mut self.reader, SeekFrom::Start(header_start))?;
+            }
+            None => {
+                let position = match self.next_entry_index.checked_sub(1) {
+                    Some(index) => {
+                        let location = &self.entry_headers[index];
+                        location.data_start + location.header.size()
+                    }
+                    None => AIX_FIXED_HEADER_LEN,
+                };
+                if header_start < position {
+                    let msg = format!(
+                        "AIX archive member at offset {} overlaps the \
+                         previous member",
+                        header_start
+                    );
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+                let gap = header_start - position;
+                let mut remaining = self.reader.by_ref().take(gap);
+                if io::copy(&mut remaining, &mut io::sink())? < gap {
+                    return Err(ArError::TruncatedHeader.into());
+                }
+            }
+        }
+        let (header, header_len, next_member) =
+            Header::read_aix(&mut self.reader, self.validating, self.lenient)?;
+        self.check_size_limit(&header)?;
+        self.check_data_in_stream(header_start + header_len, header.size())?;
+        if header_start == self.new_entry_start {
+            self.new_entry_start =
+                if header_start == self.aix_last_member || next_member == 0 {
+                    0
+                } else if next_member <= header_start {
+                    // Members out of order could otherwise form a cycle.
+                    let msg = format!(
+                    "AIX archive member at offset {} is followed by one at \
+                     earlier offset {}",
+                    header_start, next_member
+                );
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                } else {
+                    next_member
+                };
+        }
+        Ok(HeaderAndLocation {
+            header,
+            header_start,
+            data_start: header_start + header_len,
+        })
+    }
+
+    /// Returns the index of the (already-read) entry whose header starts at
+    /// the given offset, if there is one.
+    pub(crate) fn member_index_at(&self, offset: u64) -> Option<usize> {
+        self.entry_headers
+            .iter()
+            .position(|entry| entry.header_start == offset)
+    }
+
+    /// Seeks to the data of the already-read entry at the given index, and
+    /// returns that entry, so that `next_entry()` continues after it.
+    fn seek_to_entry(
+        &mut self,
+        index: usize,
+        seek: fn(&mut R, SeekFrom) -> io::Result<u64>,
+    ) -> io::Result<Entry<'_, R>> {
+        let offset = self.entry_headers[index].data_start;
+        seek(&mut self.reader, SeekFrom::Start(offset))?;
+        self.unread = 0;
+        let size = self.member_data_size(&self.entry_headers[index].header);
+        self.padding = self.variant != Variant::AIX && !size.is_multiple_of(2);
+        self.peeked = false;
+        self.next_entry_index = index + 1;
+        let location = &self.entry_headers[index];
+        Ok(Entry {
+            header: &location.header,
+            member_offset: location.header_start,
+            data_offset: location.data_start,
+            reader: self.reader.by_ref(),
+            length: size,
+            position: 0,
+            stream_data_start: None,
+            thin: self.thin,
+            unread: &mut self.unread,
+        })
+    }
+
+    /// Finds the first entry with the given identifier and copies its data
+    /// into `out`, returning `Ok(false)` if there is no such entry.  Entries
+    /// after the current position are searched by reading forward (skipping
+    /// their data); `next_entry()` then continues after the entry found, or
+    /// returns `None` if none was.
+    ///
+    /// If the entry has already been passed, it can only be gone back to
+    /// with a seekable reader (see `Archive::new_seekable()`).  Otherwise,
+    /// the first entry with that identifier after the current position is
+    /// used instead, and if there is none, an error is returned.
+    pub fn extract_to<W: Write>(
+        &mut self,
+        identifier: &[u8],
+        out: &mut W,
+    ) -> Result<bool> {
+        let passed = self.entry_headers[..self.next_entry_index]
+            .iter()
+            .position(|entry| entry.header.identifier() == identifier);
+        let mut entry = match (passed, self.seek) {
+            (Some(index), Some(seek)) => self.seek_to_entry(index, seek)?,
+            (passed, _) => loop {
+                match self.next_entry() {
+                    Some(Ok(entry)) => {
+                        if entry.header().identifier() == identifier {
+                            break entry;
+                        }
+                    }
+                    Some(Err(error)) => return Err(error),
+                    None if passed.is_some() => {
+                        let msg = format!(
+                            "Entry {:?} comes before the current position, \
+                             and the archive isn't seekable",
+                            String::from_utf8_lossy(identifier)
+                        );
+                        return Err(
+                            Error::new(ErrorKind::InvalidInput, msg).into()
+                        );
+                    }
+                    None => return Ok(false),
+                }
+            },
+        };
+        let expected = entry.bytes_remaining();
+        let actual = io::copy(&mut entry, out)?;
+        if actual < expected {
+            return Err(ArError::TruncatedData { expected, actual });
+        }
+        Ok(true)
+    }
+
+    /// Returns the identifiers of all entries in the archive (not counting
+    /// special entries, such as the GNU archive name table or symbol table),
+    /// with long filenames resolved.  Entries that haven't been read yet are
+    /// skipped over without being extracted; afterwards, `next_entry()`
+    /// returns `None`.
+    pub fn names(&mut self) -> Result<Vec<Vec<u8>>> {
+        self.read_remaining_headers()?;
+        Ok(self
+            .entry_headers
+            .iter()
+            .map(|entry| entry.header.identifier().to_vec())
+            .collect())
+    }
+
+    /// Returns a copy of the headers of all entries in the archive (not
+    /// counting special entries), which, unlike an `Entry`, doesn't borrow
+    /// the archive.  Like `names()`, this skips over any entries that haven't
+    /// been read yet; for a seekable reader, an entry's data can then be read
+    /// with `jump_to_entry()`, using the header's index in the result.
+    pub fn headers(&mut self) -> Result<Vec<Header>> {
+        self.read_remaining_headers()?;
+        Ok(self
+            .entry_headers
+            .iter()
+            .map(|entry| entry.header.clone())
+            .collect())
+    }
+
+    /// Returns the total number of entries in the archive (not counting
+    /// special entries, such as the GNU archive name table or symbol table).
+    /// Like `names()`, this skips over any entries that haven't been read
+    /// yet.  For a seekable reader, `count_entries()` does the same without
+    /// affecting the entries returned by `next_entry()`.
+    pub fn len(&mut self) -> Result<usize> {
+        self.read_remaining_headers()?;
+        Ok(self.entry_headers.len())
+    }
+
+    /// Returns true if the archive has no entries (not counting special
+    /// entries).  Like `len()`, this skips over any entries that haven't been
+    /// read yet.
+    pub fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn read_remaining_headers(&mut self) -> io::Result<()> {
+        if self.scanned {
+            self.next_entry_index = self.entry_headers.len();
+            self.peeked = false;
+            return Ok(());
+        }
+        while let Some(entry) = self.next_entry() {
+            entry?;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` on each of the remaining entries in this archive, in order,
+    /// stopping at the first error returned either while reading an entry or
+    /// by `f` itself.
+    ///
+    /// Each entry borrows the archive, so it can't outlive the call to `f`
+    /// that it is passed to; any of its data that `f` doesn't read is skipped
+    /// before the next entry is read.
+    pub fn for_each_entry<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Entry<R>) -> Result<()>,
+    {
+        while let Some(entry) = self.next_entry() {
+            f(entry?)?;
+        }
+        Ok(())
+    }
+
+    /// Walks the remaining entries in this archive without extracting them,
+    /// checking that the archive is well-formed.  This is a cheap way to
+    /// check that a file is a valid archive before committing to processing
+    /// it, and is meant to be called before reading any entries.
+    ///
+    /// In addition to the checks that `next_entry()` always makes (that the
+    /// global header is present, that the numeric fields of each entry header
+    /// parse, and that padding bytes are newlines or NULs), this also checks
+    /// that each entry header ends with the "`\n" terminator and that no
+    /// entry's data runs past the end of the archive.  Returns the first error
+    /// encountered, as an `Error::InvalidMember` giving the offset of the
+    /// offending member's header.  Afterwards, `next_entry()` returns `None`.
+    pub fn validate(&mut self) -> Result<()> {
+        self.validating = true;
+        let result = self.validate_entries();
+        self.validating = false;
+        result
+    }
+
+    fn validate_entries(&mut self) -> Result<()> {
+        loop {
+            let result = match self.next_entry() {
+                None => return Ok(()),
+                Some(Ok(mut entry)) => {
+                    Ok(read_all_data(&mut entry).map_err(ArError::from))
+                }
+                Some(Err(error)) => Err(error),
+            };
+            let (offset, error) = match result {
+                Ok(Ok(())) => continue,
+                // The entry we just read failed partway through its data.
+                Ok(Err(error)) => {
+                    let index = self.next_entry_index - 1;
+                    (self.entry_headers[index].header_start, error)
+                }
+                // The global header itself is bad.
+                Err(error) if !self.started => return Err(error),
+                Err(error) => (self.new_entry_start, error),
+            };
+            // The offset given says which member the error was found in.
+            let error = error.without_context();
+            let offset = match error {
+                // The bad byte comes just before the next header.
+                ArError::InvalidPadding(_) => offset - 1,
+                _ => offset,
+            };
+            let error =
+                ArError::InvalidMember { offset, error: Box::new(error) };
+            return Err(error);
+        }
+    }
+
+    /// Sets whether `unpack()` gives each extracted file the mode from its
+    /// entry header.  This only has an effect on Unix, and is disabled by
+    /// default, so that an untrusted archive can't create e.g. setuid or
+    /// world-writable files.
+    pub fn set_preserve_permissions(&mut self, preserve: bool) {
+        self.preserve_permissions = preserve;
+    }
+
+    /// Sets whether `unpack()` gives each extracted file the modification
+    /// time from its entry header, rather than leaving it as the time of
+    /// extraction.  This is disabled by default.
+    pub fn set_preserve_mtime(&mut self, preserve: bool) {
+        self.preserve_mtime = preserve;
+    }
+
+    /// Extracts every entry in this archive into the `dest` directory, using
+    /// each entry's identifier as its path relative to `dest`.  Parent
+    /// directories are created as needed.  If enabled with
+    /// `set_preserve_mtime()` and `set_preserve_permissions()`, each file's
+    /// modification time and (on Unix) permissions are set from the entry
+    /// header; the file type bits of its mode are ignored.
+    ///
+    /// Both `/` and `\` are treated as path separators in identifiers, so
+    /// that archives written on Windows unpack into the intended directories
+    /// everywhere.  Returns an error without writing anything for an entry
+    /// whose identifier is an absolute path or contains `..`, since it would
+    /// be extracted outside of `dest`.  Thin archives can't be unpacked, since
+    /// they don't contain their members' data.
+    pub fn unpack<P: AsRef<Path>>(self, dest: P) -> Result<()> {
+        self.unpack_with_progress(dest, |_, _, _| {})
+    }
+
+    /// Extracts every entry in this archive into the `dest` directory, like
+    /// `unpack()`, calling `progress` with each entry's header, the number of
+    /// bytes of its data written so far, and its total size.  This is called
+    /// once with zero bytes written when an entry is started, and then after
+    /// every 8 KiB written (and after the last, possibly shorter, chunk), so
+    /// that progress can be reported during a single large entry as well.
+    pub fn unpack_with_progress<P, F>(
+        mut self,
+        dest: P,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Header, u64, u64),
+    {
+        let dest = dest.as_ref();
+        let preserve_permissions = self.preserve_permissions;
+        let preserve_mtime = self.preserve_mtime;
+        while let Some(entry) = self.next_entry() {
+            let mut entry = entry?;
+            if entry.thin {
+                let msg = "Cannot unpack a thin archive";
+                return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+            }
+            let path = unpack_path(dest, entry.header().identifier())?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(&path)?;
+            let header = entry.header().clone();
+            let total = entry.bytes_remaining();
+            progress(&header, 0, total);
+            let mut buffer = [0; UNPACK_CHUNK_SIZE];
+            let mut written = 0;
+            loop {
+                let len = match entry.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {
+                        continue
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                file.write_all(&buffer[..len])?;
+                written += len as u64;
+                progress(&header, written, total);
+            }
+            if preserve_mtime {
+                if let Some(mtime) = entry.header().modified() {
+                    file.set_modified(mtime)?;
+                }
+            }
+            if preserve_permissions {
+                set_mode(&path, entry.header().permissions())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and discards the rest of the entry's data, returning an error if
+/// the archive ends before all of it has been read.
+fn read_all_data<R: Read>(entry: &mut Entry<'_, R>) -> io::Result<()> {
+    let expected = entry.bytes_remaining();
+    let actual = io::copy(entry, &mut io::sink())?;
+    if actual < expected {
+        return Err(ArError::TruncatedData { expected, actual }.into());
+    }
+    Ok(())
+}
+
+/// Returns the path that an entry with the given identifier should be
+/// unpacked to, or an error if it would end up outside of `dest`.  Both `/`
+/// and `\` separate components of the identifier, since archives written on
+/// Windows may use either.
+fn unpack_path(dest: &Path, identifier: &[u8]) -> io::Result<PathBuf> {
+    let outside = || {
+        let msg = format!(
+            "Entry identifier {:?} would be unpacked outside of the \
+             destination directory",
+            String::from_utf8_lossy(identifier)
+        );
+        Error::new(ErrorKind::InvalidData, msg)
+    };
+    let is_separator = |byte: &u8| *byte == b'/' || *byte == b'\\';
+    if identifier.first().is_some_and(is_separator) {
+        return Err(outside());
+    }
+    let mut path = dest.to_path_buf();
+    for part in identifier.split(is_separator) {
+        match part {
+            b"" | b"." => continue,
+            b".." => return Err(outside()),
+            _ => {}
+        }
+        // On Windows, a part such as `C:` is a drive prefix.
+        let part = bytes_to_path(part)?;
+        let mut components = part.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => path.push(name),
+            _ => return Err(outside()),
+        }
+    }
+    if path == dest {
+        let msg = format!(
+            "Entry identifier {:?} is not a valid file name",
+            String::from_utf8_lossy(identifier)
+        );
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, permissions: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // Archives written on non-Unix systems often leave the mode as zero;
+    // keep the default permissions rather than making the file unreadable.
+    if permissions & 0o777 == 0 {
+        return Ok(());
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(permissions))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _permissions: u32) -> io::Result<()> {
+    Ok(())
+}
+
+impl Archive<File> {
+    /// Opens the archive file at the given path for reading.  Since a file is
+    /// seekable, this is equivalent to passing the opened file to
+    /// `Archive::new_seekable()`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Archive<File>> {
+        Ok(Archive::new_seekable(File::open(path)?))
+    }
+}
+
+impl<R: Read> fmt::Debug for Archive<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The variant is only a default until the global header is read.
+        let variant = if self.started { Some(self.variant) } else { None };
+        f.debug_struct("Archive")
+            .field("variant", &variant)
+            .field("offset", &self.new_entry_start)
+            .field("entries_read", &self.next_entry_index)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<R: Read + Seek> Archive<R> {
+    /// Create a new archive reader from a seekable reader.  This is the same
+    /// as `Archive::new()`, except that any data an entry's reader doesn't
+    /// consume is skipped over by seeking, rather than by reading it and
+    /// throwing it away, when moving on to the next entry.
+    pub fn new_seekable(reader: R) -> Archive<R> {
+        let mut archive = Archive::new(reader);
+        archive.seek = Some(R::seek);
+        archive
+    }
+
     fn scan_if_necessary(&mut self) -> io::Result<()> {
+        self.seek = Some(R::seek);
         if self.scanned {
             return Ok(());
         }
         self.read_global_header_if_necessary()?;
         loop {
             let header_start = self.new_entry_start;
+            if self.variant == Variant::AIX {
+                if header_start == 0 {
+                    break;
+                }
+                let location = self.read_aix_header_at(header_start)?;
+                self.entry_headers.push(location);
+                continue;
+            }
             self.reader.seek(SeekFrom::Start(header_start))?;
             if let Some((header, header_len)) = Header::read(
                 &mut self.reader,
                 &mut self.variant,
                 &mut self.name_table,
+                self.validating,
+                self.lenient,
+                self.size_limit,
             )? {
-                let size = header.size();
+                let is_name_table = self.is_name_table_id(header.identifier());
+                let is_symbol_table =
+                    self.is_symbol_lookup_table_id(header.identifier());
+                let size = if is_name_table || is_symbol_table {
+                    header.size()
+                } else {
+                    self.member_data_size(&header)
+                };
                 self.new_entry_start += header_len + size + (size % 2);
-                if self.is_name_table_id(header.identifier()) {
+                if size % 2 != 0 {
+                    let padding_start = self.new_entry_start - 1;
+                    self.reader.seek(SeekFrom::Start(padding_start))?;
+                    if self.read_padding()?.is_some() {
+                        self.new_entry_start = padding_start;
+                    }
+                }
+                if is_name_table {
+                    self.name_table_header = Some(HeaderAndLocation {
+                        header,
+                        header_start,
+                        data_start: header_start + header_len,
+                    });
                     continue;
                 }
-                if self.is_symbol_lookup_table_id(header.identifier()) {
+                if is_symbol_table {
                     self.symbol_table_header = Some(HeaderAndLocation {
                         header,
                         header_start,
@@ -254,48 +1240,252 @@ impl<R: Read + Seek> Archive<R> {
                 break;
             }
         }
-        // Resume our previous position in the file.
+        self.resume_next_entry()?;
+        self.scanned = true;
+        Ok(())
+    }
+
+    /// Seeks back to where the next entry to be returned by `next_entry()`
+    /// starts, after reading elsewhere in the archive.
+    fn resume_next_entry(&mut self) -> io::Result<()> {
+        self.unread = 0;
+        self.peeked = false;
         if self.next_entry_index < self.entry_headers.len() {
             let offset =
                 self.entry_headers[self.next_entry_index].header_start;
             self.reader.seek(SeekFrom::Start(offset))?;
+            self.padding = false;
         }
-        self.scanned = true;
         Ok(())
     }
 
-    /// Scans the archive and returns the total number of entries in the
-    /// archive (not counting special entries, such as the GNU archive name
-    /// table or symbol table, that are not returned by `next_entry()`).
-    pub fn count_entries(&mut self) -> io::Result<usize> {
-        self.scan_if_necessary()?;
-        Ok(self.entry_headers.len())
+    /// Seeks back to the start of the archive, so that `next_entry()` returns
+    /// every entry again, starting from the first.  Entry headers that have
+    /// already been read are remembered, so this is cheap to do after a
+    /// first pass over the archive (e.g. to build an index) before a second
+    /// one (e.g. to extract some of the entries).
+    pub fn rewind(&mut self) -> Result<()> {
+        self.seek = Some(R::seek);
+        if !self.started {
+            return Ok(());
+        }
+        let start = if self.headerless { 0 } else { GLOBAL_HEADER_LEN as u64 };
+        self.reader.seek(SeekFrom::Start(start))?;
+        self.unread = 0;
+        self.padding = false;
+        self.peeked = false;
+        self.next_entry_index = 0;
+        self.error = false;
+        Ok(())
     }
 
-    /// Scans the archive and jumps to the entry at the given index.  Returns
-    /// an error if the index is not less than the result of `count_entries()`.
-    pub fn jump_to_entry(&mut self, index: usize) -> io::Result<Entry<R>> {
-        self.scan_if_necessary()?;
-        if index >= self.entry_headers.len() {
-            let msg = "Entry index out of bounds";
-            return Err(Error::new(ErrorKind::InvalidInput, msg));
+    /// After `next_entry()` has returned an error, searches forward for the
+    /// next plausible entry header (60 bytes that `Header::parse()` accepts,
+    /// ending with the header terminator) and moves there, so that
+    /// `next_entry()` continues with that entry.  Returns `Ok(false)`,
+    /// without changing anything, if there is no such header before the end
+    /// of the archive, or if there was no error to recover from.
+    ///
+    /// This is a best-effort way of salvaging the rest of a partially
+    /// corrupted archive: the entry with the corrupt header is lost, and
+    /// anything in an entry's data that happens to look like a header is
+    /// taken for one.  It is never done automatically, so that corruption
+    /// isn't silently skipped over.  AIX big archives, whose members are
+    /// found by following offsets rather than by position, can't be
+    /// resynchronized.
+    pub fn resync(&mut self) -> Result<bool> {
+        if !self.error || !self.started || self.variant == Variant::AIX {
+            return Ok(false);
         }
-        let offset = self.entry_headers[index].data_start;
-        self.reader.seek(SeekFrom::Start(offset))?;
-        let header = &self.entry_headers[index].header;
-        let size = header.size();
-        if size % 2 != 0 {
-            self.padding = true;
-        } else {
-            self.padding = false;
+        self.seek = Some(R::seek);
+        let header_start = match self.entry_headers.get(self.next_entry_index)
+        {
+            Some(location) => location.header_start,
+            None => self.new_entry_start,
+        };
+        // If the error was in the header there (rather than, say, in the
+        // padding byte before it), the header has been at least partly read,
+        // and the search starts just after its first byte.
+        let mut start = header_start;
+        if self.reader.stream_position()? > start {
+            start += 1;
         }
-        self.next_entry_index = index + 1;
-        Ok(Entry {
-            header,
+        let found = match find_header(&mut self.reader, start)? {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+        self.reader.seek(SeekFrom::Start(found))?;
+        // Headers recorded before a rewind can't be trusted past the error.
+        self.entry_headers.truncate(self.next_entry_index);
+        self.scanned = false;
+        self.new_entry_start = found;
+        self.unread = 0;
+        self.padding = false;
+        self.peeked = false;
+        self.error = false;
+        Ok(true)
+    }
+
+    /// Like `next_entry()`, but also returns the special entries that it
+    /// skips: the GNU symbol table (`/` or `/SYM64/`) and name table (`//`),
+    /// and the BSD symbol table (`__.SYMDEF`), with their raw data.  This is
+    /// for tools that need to see every member of the archive, such as
+    /// those that dump or repair archives.  Special entries that were
+    /// already passed by a call to `peek_header()` aren't returned.
+    pub fn next_entry_including_special(
+        &mut self,
+    ) -> Option<Result<Entry<'_, R>>> {
+        if !self.peeked {
+            match self.read_next_member(true) {
+                Some(Ok(())) => {}
+                Some(Err(error)) => return Some(Err(error.into())),
+                None => return None,
+            }
+            if self.special.is_none() {
+                self.peeked = true;
+            }
+        }
+        let (data_start, size) = match self.special {
+            Some(ref location) => {
+                (location.data_start, location.header.size())
+            }
+            None => return self.next_entry(),
+        };
+        // The special entry's data was read (or skipped) along with its
+        // header, so go back to it.
+        if let Err(error) = self.reader.seek(SeekFrom::Start(data_start)) {
+            return Some(Err(error.into()));
+        }
+        let location = self.special.as_ref().unwrap();
+        Some(Ok(Entry {
+            header: &location.header,
+            member_offset: location.header_start,
+            data_offset: data_start,
             reader: self.reader.by_ref(),
             length: size,
             position: 0,
-        })
+            stream_data_start: None,
+            thin: false,
+            unread: &mut self.unread,
+        }))
+    }
+
+    /// Scans the archive and returns the total number of entries in the
+    /// archive (not counting special entries, such as the GNU archive name
+    /// table or symbol table, that are not returned by `next_entry()`).
+    pub fn count_entries(&mut self) -> Result<usize> {
+        self.scan_if_necessary()?;
+        Ok(self.entry_headers.len())
+    }
+
+    /// Scans the archive and returns the identifier (with long filenames
+    /// resolved, whatever the variant), header offset, and data size of
+    /// every entry in the archive (not counting special entries), without
+    /// affecting the entries returned by `next_entry()`.  The position of an
+    /// entry in the result is its index for `jump_to_entry()`, so this can be
+    /// used to build a map for repeated lookups without rescanning.
+    pub fn index(&mut self) -> Result<Vec<(Vec<u8>, u64, u64)>> {
+        self.scan_if_necessary()?;
+        Ok(self
+            .entry_headers
+            .iter()
+            .map(|entry| {
+                let size = self.member_data_size(&entry.header);
+                (entry.header.identifier().to_vec(), entry.header_start, size)
+            })
+            .collect())
+    }
+
+    /// Scans the archive and jumps to the entry at the given index.  Returns
+    /// an error if the index is not less than the result of `count_entries()`.
+    pub fn jump_to_entry(&mut self, index: usize) -> Result<Entry<'_, R>> {
+        self.scan_if_necessary()?;
+        if index >= self.entry_headers.len() {
+            let msg = "Entry index out of bounds";
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        self.seek_to_entry(index, R::seek).map_err(ArError::from)
+    }
+
+    /// Scans the archive and jumps to the first entry with the given
+    /// identifier.  Returns `Ok(None)` if there is no such entry.  Long
+    /// filenames are resolved before comparing, so the identifier should be
+    /// the entry's full name, as returned by `Header::identifier()`.
+    pub fn find_entry(
+        &mut self,
+        identifier: &[u8],
+    ) -> Result<Option<Entry<'_, R>>> {
+        self.scan_if_necessary()?;
+        let index = self
+            .entry_headers
+            .iter()
+            .position(|entry| entry.header.identifier() == identifier);
+        match index {
+            Some(index) => self.jump_to_entry(index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Scans the archive and returns a copy of the data of the first entry
+    /// with the given identifier (as for `find_entry()`), or `Ok(None)` if
+    /// there is no such entry.  Unlike an `Entry`, the data doesn't borrow
+    /// the archive, so it can be handed off elsewhere; `next_entry()` then
+    /// continues after the entry found.
+    ///
+    /// Before anything is allocated, the entry's size is checked against the
+    /// limit set by `set_size_limit()`, and against the length of the
+    /// archive, so that a corrupt or malicious header can't cause an
+    /// enormous allocation.  Thin archive members have no data to read, so
+    /// they are an error.
+    pub fn read_member(
+        &mut self,
+        identifier: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        self.scan_if_necessary()?;
+        let index = self
+            .entry_headers
+            .iter()
+            .position(|entry| entry.header.identifier() == identifier);
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        if self.thin {
+            let msg = "Cannot read the data of a thin archive member";
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        self.check_size_limit(&self.entry_headers[index].header)?;
+        let data_start = self.entry_headers[index].data_start;
+        let size = self.member_data_size(&self.entry_headers[index].header);
+        self.check_data_in_stream(data_start, size)?;
+        let data = self.seek_to_entry(index, R::seek)?.read_all()?;
+        Ok(Some(data))
+    }
+
+    /// Scans the archive and jumps to the member that defines the given
+    /// symbol, according to the archive's symbol table.  Returns `Ok(None)`
+    /// if the archive has no symbol table or the symbol isn't in it, and an
+    /// error if the symbol table gives an offset that isn't the start of a
+    /// member.
+    pub fn entry_for_symbol(
+        &mut self,
+        name: &[u8],
+    ) -> Result<Option<Entry<'_, R>>> {
+        let offset = match self.symbols()?.lookup(name) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        match self.member_index_at(offset) {
+            Some(index) => self.jump_to_entry(index).map(Some),
+            None => {
+                let msg = format!(
+                    "Symbol table refers to a member at offset {}, but \
+                     there is no member there",
+                    offset
+                );
+                Err(Error::new(ErrorKind::InvalidData, msg).into())
+            }
+        }
     }
 
     fn parse_symbol_table_if_necessary(&mut self) -> io::Result<()> {
@@ -304,92 +1494,162 @@ impl<R: Read + Seek> Archive<R> {
             return Ok(());
         }
         if let Some(ref header_and_loc) = self.symbol_table_header {
+            self.check_size_limit(&header_and_loc.header)?;
+            let size = header_and_loc.header.size();
             let offset = header_and_loc.data_start;
             self.reader.seek(SeekFrom::Start(offset))?;
             let mut reader = BufReader::new(
                 self.reader.by_ref().take(header_and_loc.header.size()),
             );
             if self.variant == Variant::GNU {
-                let num_symbols = read_be_u32(&mut reader)? as usize;
-                let mut symbol_offsets =
-                    Vec::<u32>::with_capacity(num_symbols);
+                // The /SYM64/ table is the same, except with 64-bit offsets.
+                let wide = header_and_loc.header.identifier()
+                    == GNU_SYMBOL_LOOKUP_TABLE_64_ID;
+                let read_be =
+                    if wide { read_be_u64 } else { read_be_u32_as_u64 };
+                let num_symbols = read_be(&mut reader)? as usize;
+                let mut symbol_offsets = Vec::<u64>::new();
                 for _ in 0..num_symbols {
-                    let offset = read_be_u32(&mut reader)?;
+                    let offset = read_be(&mut reader)?;
                     symbol_offsets.push(offset);
                 }
-                let mut symbol_table = Vec::with_capacity(num_symbols);
+                let mut strings = Vec::new();
+                reader.read_to_end(&mut strings)?;
+                let mut symbols = Vec::with_capacity(num_symbols);
+                let mut start = 0;
                 for offset in symbol_offsets.into_iter() {
-                    let mut buffer = Vec::<u8>::new();
-                    reader.read_until(0, &mut buffer)?;
-                    if buffer.last() == Some(&0) {
-                        buffer.pop();
-                    }
-                    buffer.shrink_to_fit();
-                    symbol_table.push((buffer, offset as u64));
+                    symbols.push((start, offset));
+                    // The names follow one another, each ending with a NUL.
+                    start = match strings[start..].iter().position(|&b| b == 0)
+                    {
+                        Some(len) => start + len + 1,
+                        None => strings.len(),
+                    };
                 }
-                self.symbol_table = Some(symbol_table);
+                self.symbol_table = Some(SymbolTable::new(strings, symbols));
             } else {
-                let num_symbols = (read_le_u32(&mut reader)? / 8) as usize;
+                // The lengths in the table can't be trusted, so check that
+                // the entry is big enough for them before allocating.
+                let num_symbols = u64::from(read_le_u32(&mut reader)? / 8);
+                let mut table_size = 8 + num_symbols * 8;
+                check_symbol_table_size(table_size, size)?;
                 let mut symbol_offsets =
-                    Vec::<(u32, u32)>::with_capacity(num_symbols);
+                    Vec::<(u32, u32)>::with_capacity(num_symbols as usize);
                 for _ in 0..num_symbols {
                     let str_offset = read_le_u32(&mut reader)?;
                     let file_offset = read_le_u32(&mut reader)?;
                     symbol_offsets.push((str_offset, file_offset));
                 }
                 let str_table_len = read_le_u32(&mut reader)?;
+                table_size += u64::from(str_table_len);
+                check_symbol_table_size(table_size, size)?;
                 let mut str_table_data = vec![0u8; str_table_len as usize];
                 reader.read_exact(&mut str_table_data).map_err(|err| {
                     annotate(err, "failed to read string table")
                 })?;
-                let mut symbol_table = Vec::with_capacity(num_symbols);
-                for (str_start, file_offset) in symbol_offsets.into_iter() {
-                    let str_start = str_start as usize;
-                    let mut str_end = str_start;
-                    while str_end < str_table_data.len()
-                        && str_table_data[str_end] != 0u8
-                    {
-                        str_end += 1;
-                    }
-                    let string = &str_table_data[str_start..str_end];
-                    symbol_table.push((string.to_vec(), file_offset as u64));
-                }
-                self.symbol_table = Some(symbol_table);
+                let symbols = symbol_offsets
+                    .into_iter()
+                    .map(|(str_start, file_offset)| {
+                        (str_start as usize, u64::from(file_offset))
+                    })
+                    .collect();
+                self.symbol_table =
+                    Some(SymbolTable::new(str_table_data, symbols));
             }
         }
-        // Resume our previous position in the file.
-        if !self.entry_headers.is_empty() {
-            let offset =
-                self.entry_headers[self.next_entry_index].header_start;
-            self.reader.seek(SeekFrom::Start(offset))?;
-        }
+        self.resume_next_entry()?;
         Ok(())
     }
 
     /// Scans the archive and returns an iterator over the symbols in the
-    /// archive's symbol table.  If the archive doesn't have a symbol table,
-    /// this method will still succeed, but the iterator won't produce any
-    /// values.
-    pub fn symbols(&mut self) -> io::Result<Symbols<R>> {
+    /// archive's symbol table, each paired with the offset of the header of
+    /// the member that defines it.  If the archive doesn't have a symbol
+    /// table, this method will still succeed, but the iterator won't produce
+    /// any values.
+    pub fn symbols(&mut self) -> Result<Symbols<'_, R>> {
         self.parse_symbol_table_if_necessary()?;
         Ok(Symbols { archive: self, index: 0 })
     }
 }
 
+/// Returns an error if a BSD symbol table whose lengths add up to
+/// `table_size` bytes can't fit in an entry with `entry_size` bytes of data.
+fn check_symbol_table_size(
+    table_size: u64,
+    entry_size: u64,
+) -> io::Result<()> {
+    if table_size > entry_size {
+        let msg = format!(
+            "Symbol table needs {} bytes, but its entry has only {}",
+            table_size, entry_size
+        );
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    Ok(())
+}
+
+/// Returns the offset of the first plausible entry header at or after
+/// `start`, for `Archive::resync()`.
+fn find_header<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+) -> io::Result<Option<u64>> {
+    let mut buffer = Vec::with_capacity(RESYNC_CHUNK_SIZE);
+    let mut position = start;
+    loop {
+        reader.seek(SeekFrom::Start(position))?;
+        buffer.clear();
+        let mut chunk = reader.by_ref().take(RESYNC_CHUNK_SIZE as u64);
+        let len = chunk.read_to_end(&mut buffer)?;
+        let windows = buffer.windows(ENTRY_HEADER_LEN);
+        for (offset, window) in windows.enumerate() {
+            if !window.ends_with(b"`\n") {
+                continue;
+            }
+            let mut header = [0; ENTRY_HEADER_LEN];
+            header.copy_from_slice(window);
+            if Header::parse(&header).is_ok() {
+                return Ok(Some(position + offset as u64));
+            }
+        }
+        if len < RESYNC_CHUNK_SIZE {
+            return Ok(None);
+        }
+        // Overlap the chunks, so that a header straddling two is found.
+        position += (len - ENTRY_HEADER_LEN + 1) as u64;
+    }
+}
+
 fn read_le_u32(r: &mut impl io::Read) -> io::Result<u32> {
     let mut buf = [0; 4];
     r.read_exact(&mut buf).map(|()| u32::from_le_bytes(buf))
 }
 
-fn read_be_u32(r: &mut impl io::Read) -> io::Result<u32> {
+fn read_be_u32_as_u64<R: io::Read>(r: &mut R) -> io::Result<u64> {
     let mut buf = [0; 4];
-    r.read_exact(&mut buf).map(|()| u32::from_be_bytes(buf))
+    r.read_exact(&mut buf).map(|()| u32::from_be_bytes(buf).into())
+}
+
+fn read_be_u64<R: io::Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf).map(|()| u64::from_be_bytes(buf))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Archive, GnuBuilder, Header, Variant};
-    use std::io::{Cursor, Read, Result, Seek, SeekFrom};
+    use crate::header::ENTRY_HEADER_LEN;
+    use crate::{
+        Archive, Builder, Error, GnuBuilder, Header, ParsedKind,
+        SymbolTableKind, Variant,
+    };
+    use std::env;
+    use std::fs;
+    use std::io::{
+        self, BufRead, Cursor, ErrorKind, Read, Result, Seek, SeekFrom,
+    };
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{Duration, UNIX_EPOCH};
 
     struct SlowReader<'a> {
         current_position: usize,
@@ -403,7 +1663,7 @@ mod tests {
             }
             buf[0] = self.buffer[self.current_position];
             self.current_position += 1;
-            return Ok(1);
+            Ok(1)
         }
     }
 
@@ -689,6 +1949,50 @@ mod tests {
         assert!(archive.next_entry().is_none());
     }
 
+    #[test]
+    fn read_gnu_archive_including_special_entries() {
+        let input = b"\
+        !<arch>\n\
+        /               0           0     0     0       15        `\n\
+        \x00\x00\x00\x01\x00\x00\x00\xb2foobar\x00\n\
+        //                                              34        `\n\
+        this_is_a_very_long_filename.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let mut entries = Vec::new();
+        while let Some(entry) = archive.next_entry_including_special() {
+            let mut entry = entry.unwrap();
+            let identifier = entry.header().identifier().to_vec();
+            let offset = entry.member_offset();
+            entries.push((identifier, offset, entry.read_all().unwrap()));
+        }
+        let expected: Vec<(&[u8], u64, &[u8])> = vec![
+            (b"/", 8, b"\x00\x00\x00\x01\x00\x00\x00\xb2foobar\x00"),
+            (b"//", 84, b"this_is_a_very_long_filename.txt/\n"),
+            (b"this_is_a_very_long_filename.txt", 178, b"foobar\n"),
+        ];
+        let expected: Vec<(Vec<u8>, u64, Vec<u8>)> = expected
+            .into_iter()
+            .map(|(id, offset, data)| (id.to_vec(), offset, data.to_vec()))
+            .collect();
+        assert_eq!(entries, expected);
+        assert_eq!(archive.symbols().unwrap().count(), 1);
+
+        // Special entries that aren't read are skipped as usual, and mixing
+        // in next_entry() still gives only the real entries.
+        archive.rewind().unwrap();
+        {
+            let entry = archive.next_entry_including_special().unwrap();
+            assert_eq!(entry.unwrap().header().identifier(), b"/");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+        }
+        assert!(archive.next_entry_including_special().is_none());
+    }
+
     #[test]
     fn read_archive_with_no_padding_byte_in_final_entry() {
         let input = "\
@@ -807,6 +2111,30 @@ mod tests {
         archive.next_entry().unwrap().unwrap();
     }
 
+    #[test]
+    fn read_lines_from_entry() {
+        let input = "\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  12        `\n\
+        foo\nbar\nbaz\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        quux";
+        let mut archive = Archive::new(input.as_bytes());
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            let lines: Vec<String> =
+                entry.lines().map(|line| line.unwrap()).collect();
+            assert_eq!(lines, vec!["foo", "bar", "baz"]);
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            let mut buffer = Vec::new();
+            entry.read_until(b'\n', &mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], "quux".as_bytes());
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
     #[test]
     fn seek_within_entry() {
         let input = "\
@@ -852,6 +2180,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn seek_within_entry_of_archive_after_prefix() {
+        // The archive doesn't start at the start of the stream.
+        let mut input = b"PREFIX!\n".to_vec();
+        input.extend_from_slice(
+            b"\
+            !<arch>\n\
+            foo.txt         1487552916  501   20    100644  10        `\n\
+            0123456789",
+        );
+        let mut cursor = Cursor::new(input);
+        cursor.seek(SeekFrom::Start(8)).unwrap();
+        let mut archive = Archive::new(cursor);
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        let mut buffer = [0; 3];
+        entry.read_exact(&mut buffer).unwrap();
+        entry.seek(SeekFrom::Start(3)).unwrap();
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"345");
+        entry.seek(SeekFrom::End(-2)).unwrap();
+        entry.read_exact(&mut buffer[..2]).unwrap();
+        assert_eq!(&buffer[..2], b"89");
+    }
+
+    #[test]
+    fn seek_within_entry_after_partial_reads() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        #1/16           1487552919  501   20    100644  46        `\n\
+        bar.awesome.txt\0abcdefghij0123456789ABCDEFGHIJ";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.next_entry().unwrap().unwrap();
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        let mut buffer = [0; 5];
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"abcde");
+        entry.seek(SeekFrom::End(-5)).unwrap();
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"FGHIJ");
+        // Even if the underlying reader is moved behind the entry's back,
+        // seeking to an absolute position within the entry still works.
+        entry.reader.seek(SeekFrom::Start(0)).unwrap();
+        entry.seek(SeekFrom::Start(10)).unwrap();
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"01234");
+        entry.reader.seek(SeekFrom::End(0)).unwrap();
+        entry.seek(SeekFrom::End(-10)).unwrap();
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"ABCDE");
+    }
+
     #[test]
     #[should_panic(expected = "Invalid seek to negative position (-17)")]
     fn seek_entry_to_negative_position() {
@@ -877,6 +2258,43 @@ mod tests {
         entry.seek(SeekFrom::Start(47)).unwrap();
     }
 
+    #[test]
+    fn seek_entry_to_extreme_positions() {
+        let input = "\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  30        `\n\
+        abcdefghij0123456789ABCDEFGHIJ";
+        let mut archive = Archive::new(Cursor::new(input.as_bytes()));
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        entry.seek(SeekFrom::Start(10)).unwrap();
+        let extremes = [
+            SeekFrom::Start(u64::MAX),
+            SeekFrom::Start(i64::MAX as u64 + 1),
+            SeekFrom::End(i64::MAX),
+            SeekFrom::End(i64::MIN),
+            SeekFrom::Current(i64::MAX),
+            SeekFrom::Current(i64::MIN),
+        ];
+        for &pos in extremes.iter() {
+            let error = entry.seek(pos).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{:?}", pos);
+        }
+        let error = entry.seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid seek to position past end of entry \
+             (18446744073709551615 vs. 30)"
+        );
+        let error = entry.seek(SeekFrom::Current(i64::MIN)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid seek to negative position (-9223372036854775798)"
+        );
+        // A failed seek doesn't move the entry.
+        assert_eq!(entry.stream_position().unwrap(), 10);
+        assert_eq!(entry.seek(SeekFrom::End(-30)).unwrap(), 0);
+    }
+
     #[test]
     fn count_entries_in_bsd_archive() {
         let input = b"\
@@ -1071,106 +2489,2195 @@ mod tests {
     }
 
     #[test]
-    fn list_symbols_in_bsd_archive() {
+    fn find_entry_in_gnu_archive() {
         let input = b"\
         !<arch>\n\
-        #1/12           0           0     0     0       60        `\n\
-        __.SYMDEF\x00\x00\x00\x18\x00\x00\x00\
-        \x00\x00\x00\x00\x80\x00\x00\x00\
-        \x07\x00\x00\x00\x80\x00\x00\x00\
-        \x0b\x00\x00\x00\x80\x00\x00\x00\
-        \x10\x00\x00\x00foobar\x00baz\x00quux\x00\
-        foo.o/          1487552916  501   20    100644  16        `\n\
-        foobar,baz,quux\n";
+        //                                              34        `\n\
+        this_is_a_very_long_filename.txt/\n\
+        hello.txt/      1487552316  42    12345 100644  14        `\n\
+        Hello, world!\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552349  42    12345 100664  4         `\n\
+        baz\n";
         let mut archive = Archive::new(Cursor::new(input as &[u8]));
-        assert_eq!(archive.symbols().unwrap().len(), 3);
-        assert_eq!(archive.variant(), Variant::BSD);
-        let symbols = archive.symbols().unwrap().collect::<Vec<&[u8]>>();
-        let expected: Vec<&[u8]> = vec![b"foobar", b"baz", b"quux"];
-        assert_eq!(symbols, expected);
+        {
+            let mut entry = archive
+                .find_entry(b"this_is_a_very_long_filename.txt")
+                .unwrap()
+                .unwrap();
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], "foobar\n".as_bytes());
+        }
+        {
+            // Reading continues from the entry after the one we found.
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), "baz.txt".as_bytes());
+        }
+        {
+            let mut entry = archive.find_entry(b"hello.txt").unwrap().unwrap();
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], "Hello, world!\n".as_bytes());
+        }
+        assert!(archive.find_entry(b"/0").unwrap().is_none());
+        assert!(archive.find_entry(b"missing.txt").unwrap().is_none());
     }
 
     #[test]
-    fn list_sorted_symbols_in_bsd_archive() {
+    fn read_gnu_thin_archive() {
         let input = b"\
-        !<arch>\n\
-        #1/16           0           0     0     0       64        `\n\
-        __.SYMDEF SORTED\x18\x00\x00\x00\
-        \x00\x00\x00\x00\x80\x00\x00\x00\
-        \x04\x00\x00\x00\x80\x00\x00\x00\
-        \x0b\x00\x00\x00\x80\x00\x00\x00\
-        \x10\x00\x00\x00baz\x00foobar\x00quux\x00\
-        foo.o/          1487552916  501   20    100644  16        `\n\
-        foobar,baz,quux\n";
+        !<thin>\n\
+        //                                              22        `\n\
+        dir/foo.o/\n\
+        bar/baz.o/\n\
+        /0              0           0     0     644     1234      `\n\
+        /11             0           0     0     644     7         `\n";
         let mut archive = Archive::new(Cursor::new(input as &[u8]));
-        assert_eq!(archive.symbols().unwrap().len(), 3);
-        assert_eq!(archive.variant(), Variant::BSD);
-        let symbols = archive.symbols().unwrap().collect::<Vec<&[u8]>>();
-        let expected: Vec<&[u8]> = vec![b"baz", b"foobar", b"quux"];
-        assert_eq!(symbols, expected);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), "dir/foo.o".as_bytes());
+            assert_eq!(entry.header().size(), 1234);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert!(buffer.is_empty());
+        }
+        assert!(archive.is_thin());
+        assert_eq!(archive.variant(), Variant::GNU);
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), "bar/baz.o".as_bytes());
+            assert_eq!(entry.header().size(), 7);
+        }
+        assert!(archive.next_entry().is_none());
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        let entry = archive.jump_to_entry(1).unwrap();
+        assert_eq!(entry.header().identifier(), "bar/baz.o".as_bytes());
     }
 
     #[test]
-    fn list_symbols_in_gnu_archive() {
+    fn read_gnu_names_ending_with_slash() {
+        // Names that end with a slash themselves (as a directory path in a
+        // thin archive might) lose only their terminating slash.
         let input = b"\
         !<arch>\n\
-        /               0           0     0     0       32        `\n\
-        \x00\x00\x00\x03\x00\x00\x00\x5c\x00\x00\x00\x5c\x00\x00\x00\x5c\
-        foobar\x00baz\x00quux\x00\
-        foo.o/          1487552916  501   20    100644  16        `\n\
-        foobar,baz,quux\n";
-        let mut archive = Archive::new(Cursor::new(input as &[u8]));
-        assert_eq!(archive.symbols().unwrap().len(), 3);
-        assert_eq!(archive.variant(), Variant::GNU);
-        let symbols = archive.symbols().unwrap().collect::<Vec<&[u8]>>();
-        let expected: Vec<&[u8]> = vec![b"foobar", b"baz", b"quux"];
-        assert_eq!(symbols, expected);
+        //                                              6         `\n\
+        bar//\n\
+        foo//           0           0     0     644     4         `\n\
+        foo\n\
+        /0              0           0     0     644     4         `\n\
+        bar\n";
+        let mut archive = Archive::new(input as &[u8]);
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo/");
+        }
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"bar/");
+        }
+        assert!(archive.next_entry().is_none());
     }
 
     #[test]
-    fn non_multiple_of_two_long_ident_in_gnu_archive() {
-        let mut buffer = std::io::Cursor::new(Vec::new());
+    fn resync_after_corrupt_header() {
+        let mut builder = Builder::new(Vec::new());
+        let names: Vec<String> =
+            (0..5).map(|index| format!("member{}.txt", index)).collect();
+        for name in &names {
+            let data = format!("data for {}\n", name);
+            let header =
+                Header::new(name.clone().into_bytes(), data.len() as u64);
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        let input = builder.into_inner().unwrap();
+        // Every member is 60 + 22 bytes, so the third starts at 172.
+        let corrupt_start = 8 + 2 * 82;
+        assert_eq!(&input[corrupt_start..corrupt_start + 7], b"member2");
 
-        {
-            let filenames = vec![
-                b"rust.metadata.bin".to_vec(),
-                b"compiler_builtins-78891cf83a7d3547.dummy_name.rcgu.o"
-                    .to_vec(),
-            ];
-            let mut builder = GnuBuilder::new(&mut buffer, filenames.clone());
+        let mut errors = 0;
+        for position in corrupt_start..corrupt_start + ENTRY_HEADER_LEN {
+            let mut corrupted = input.clone();
+            corrupted[position] = b'?';
+            let mut archive = Archive::new_seekable(Cursor::new(corrupted));
+            let mut recovered = Vec::new();
+            let mut failed = false;
+            loop {
+                let error = match archive.next_entry() {
+                    Some(Ok(mut entry)) => {
+                        let name =
+                            entry.header().identifier_str().into_owned();
+                        let data = entry.read_all().unwrap();
+                        if names.contains(&name) {
+                            let expected = format!("data for {}\n", name);
+                            assert_eq!(data, expected.as_bytes());
+                        }
+                        recovered.push(name);
+                        continue;
+                    }
+                    Some(Err(error)) => error,
+                    None => break,
+                };
+                assert!(!failed, "{}: {}", position, error);
+                failed = true;
+                assert!(archive.resync().unwrap());
+            }
+            if failed {
+                errors += 1;
+                let mut expected = names.clone();
+                expected.remove(2);
+                assert_eq!(recovered, expected, "{}", position);
+            }
+            assert!(!archive.resync().unwrap());
+        }
+        // Corrupting the identifier or (since it's only checked when
+        // validating) the terminator doesn't make the header invalid, but
+        // corrupting any of the numeric fields does.
+        assert_eq!(errors, ENTRY_HEADER_LEN - 16 - 2);
+    }
 
-            for filename in filenames {
-                builder
-                    .append(&Header::new(filename, 1), &mut (&[b'?'] as &[u8]))
-                    .expect("add file");
+    #[test]
+    fn resync_finds_nothing_after_last_header() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         0           0     0     644     4         `\n\
+        foo\n\
+        bar.txt         0           0     0     644     4?        `\n\
+        bar\n";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        archive.next_entry().unwrap().unwrap();
+        match archive.next_entry() {
+            Some(Err(error)) => {
+                assert_eq!(error.kind(), ErrorKind::InvalidData)
             }
+            _ => panic!("expected an error"),
         }
+        assert!(!archive.resync().unwrap());
+        assert!(archive.next_entry().is_none());
+    }
 
-        buffer.set_position(0);
+    #[test]
+    fn open_thin_archive_member() {
+        let dir =
+            env::temp_dir().join(format!("ar-archive-thin-{}", process::id()));
+        fs::create_dir_all(dir.join("dir")).unwrap();
+        fs::write(dir.join("dir/foo.o"), b"foobar\n").unwrap();
+        let input = b"\
+        !<thin>\n\
+        //                                              12        `\n\
+        dir/foo.o/\n\n\
+        /0              0           0     0     644     7         `\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let entry = archive.next_entry().unwrap().unwrap();
+        let mut buffer = Vec::new();
+        let mut file = entry.open_thin_member(&dir).unwrap();
+        file.read_to_end(&mut buffer).unwrap();
+        assert_eq!(&buffer as &[u8], "foobar\n".as_bytes());
+        fs::remove_dir_all(&dir).unwrap();
 
-        let mut archive = Archive::new(buffer);
-        while let Some(entry) = archive.next_entry() {
-            entry.unwrap();
-        }
+        let input = b"!<arch>\nfoo.txt         0           0     0     644     0         `\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let entry = archive.next_entry().unwrap().unwrap();
+        let error = entry.open_thin_member(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
     }
 
-    /// Regression test for https://github.com/mdsteele/rust-ar/issues/22
     #[test]
-    #[should_panic(expected = "GNU filename index out of range")]
-    fn issue_22() {
-        let data = &[
-            33, 60, 97, 114, 99, 104, 62, 10, 99, 104, 60, 159, 149, 33, 62,
-            10, 219, 87, 219, 219, 219, 96, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 48, 48, 48, 48, 48, 47, 48, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 49, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 39, 48, 48, 48,
-            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 48, 48, 48, 51, 49, 50, 56, 48, 48, 54, 54, 54, 51, 52, 56,
-            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
-            48, 48, 48, 48, 48, 48, 48, 48, 47, 48, 0, 40,
-        ];
-        let mut archive = Archive::new(std::io::Cursor::new(data));
-        let _num_entries = archive.count_entries().unwrap();
+    fn recover_structured_errors() {
+        let input = b"!<arch>\nfoo.txt         1487552916  501";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        match error.without_context() {
+            Error::TruncatedHeader => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Converting to an `io::Error` keeps the `ar::Error` as its payload,
+        // for implementations of `Read` and `Write`.
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  10        `\n\
+        foo\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => io::Error::from(error),
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let error = Error::from(error);
+        match error {
+            Error::InMember { offset: Some(8), .. } => {}
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let error = io::Error::other("disk on fire");
+        match Error::from(error) {
+            Error::Io(error) => assert_eq!(error.to_string(), "disk on fire"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let bytes_read = self.inner.read(buf)?;
+            self.bytes_read += bytes_read;
+            Ok(bytes_read)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn archive_with_large_entries() -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        let data = vec![b'x'; 100_000];
+        for name in &[b"foo.bin", b"bar.bin"] {
+            let header = Header::new(name.to_vec(), data.len() as u64);
+            builder.append(&header, data.as_slice()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn skip_unread_entry_data_by_seeking() {
+        let input = archive_with_large_entries();
+        let reader =
+            CountingReader { inner: Cursor::new(&input), bytes_read: 0 };
+        let mut archive = Archive::new_seekable(reader);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            let mut buffer = [0; 10];
+            entry.read_exact(&mut buffer).unwrap();
+        }
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"bar.bin");
+        }
+        assert!(archive.next_entry().is_none());
+        let reader = archive.into_inner().unwrap();
+        // Only the headers and the ten bytes we asked for were read.
+        assert_eq!(reader.bytes_read, 8 + 60 + 10 + 60);
+
+        let reader =
+            CountingReader { inner: Cursor::new(&input), bytes_read: 0 };
+        let mut archive = Archive::new(reader);
+        while let Some(entry) = archive.next_entry() {
+            entry.unwrap();
+        }
+        let reader = archive.into_inner().unwrap();
+        assert_eq!(reader.bytes_read, input.len());
+    }
+
+    #[test]
+    fn count_entries_after_reading_odd_sized_entry() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+        }
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"bar.txt");
+        assert_eq!(entry.read_all().unwrap(), b"baz\n");
+    }
+
+    #[test]
+    fn read_member_data() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         0           0     0     644     7         `\n\
+        foobar\n\n\
+        #1/20           0           0     0     644     24        `\n\
+        a_long_name.txt\x00\x00\x00\x00\x00bar\n\
+        baz.txt         0           0     0     644     4         `\n\
+        baz\n";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        let data = archive.read_member(b"a_long_name.txt").unwrap();
+        assert_eq!(data, Some(b"bar\n".to_vec()));
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"baz.txt");
+        }
+        let data = archive.read_member(b"foo.txt").unwrap();
+        assert_eq!(data, Some(b"foobar\n".to_vec()));
+        assert_eq!(archive.read_member(b"missing.txt").unwrap(), None);
+
+        archive.set_size_limit(Some(5));
+        let error = archive.read_member(b"foo.txt").unwrap_err();
+        match error.without_context() {
+            Error::SizeLimitExceeded { size: 7, limit: 5 } => {}
+            other => panic!("unexpected error: {}", other),
+        }
+        assert_eq!(
+            archive.read_member(b"baz.txt").unwrap().unwrap(),
+            b"baz\n"
+        );
+
+        // A declared size far beyond the end of the archive is reported
+        // without trying to allocate that much.
+        let input = b"\
+        !<arch>\n\
+        big.bin         0           0     0     644     9999999999`\n\
+        short\n";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        let error = archive.read_member(b"big.bin").unwrap_err();
+        match error.without_context() {
+            Error::TruncatedData { expected: 9999999999, actual: 6 } => {}
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn report_symbol_table_kind() {
+        fn gnu_archive(symbols: &[&[u8]]) -> Vec<u8> {
+            let names = vec![b"foo.o".to_vec()];
+            let mut builder =
+                GnuBuilder::new_with_symbol_table(Vec::new(), names);
+            let header = Header::new(b"foo.o".to_vec(), 4);
+            builder
+                .append_with_symbols(&header, "foo\n".as_bytes(), symbols)
+                .unwrap();
+            builder.into_inner().unwrap()
+        }
+
+        fn bsd_archive(sort: bool) -> Vec<u8> {
+            let mut builder = Builder::new_with_symbol_table(Vec::new());
+            builder.set_sort_symbols(sort);
+            let header = Header::new(b"foo.o".to_vec(), 4);
+            builder
+                .append_with_symbols(&header, "foo\n".as_bytes(), &[b"foo"])
+                .unwrap();
+            builder.into_inner().unwrap()
+        }
+
+        fn kind(input: Vec<u8>) -> SymbolTableKind {
+            let mut archive = Archive::new_seekable(Cursor::new(input));
+            let kind = archive.symbols().unwrap().kind();
+            kind
+        }
+
+        assert_eq!(
+            kind(gnu_archive(&[b"bar", b"baz", b"foo"])),
+            SymbolTableKind::GnuSorted
+        );
+        assert_eq!(
+            kind(gnu_archive(&[b"foo", b"bar"])),
+            SymbolTableKind::GnuUnsorted
+        );
+        assert_eq!(kind(bsd_archive(true)), SymbolTableKind::BsdSorted);
+        assert_eq!(kind(bsd_archive(false)), SymbolTableKind::BsdUnsorted);
+
+        let mut input = b"!<arch>\n".to_vec();
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "/SYM64/", 0, 0, 0, 0, 8
+        );
+        input.extend_from_slice(header.as_bytes());
+        input.extend_from_slice(&0u64.to_be_bytes());
+        assert_eq!(kind(input), SymbolTableKind::Gnu64);
+
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"foo.o".to_vec(), 4);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let input = builder.into_inner().unwrap();
+        assert_eq!(kind(input), SymbolTableKind::None);
+    }
+
+    #[test]
+    fn read_symbol_string_tables() {
+        // The last GNU symbol name isn't NUL-terminated.
+        let mut input = b"!<arch>\n".to_vec();
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "/", 0, 0, 0, 0, 27
+        );
+        input.extend_from_slice(header.as_bytes());
+        input.extend_from_slice(&3u32.to_be_bytes());
+        for _ in 0..3 {
+            input.extend_from_slice(&96u32.to_be_bytes());
+        }
+        input.extend_from_slice(b"foo\x00bar\x00baz\n");
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "foo.o/", 0, 0, 0, 644, 4
+        );
+        input.extend_from_slice(header.as_bytes());
+        input.extend_from_slice(b"foo\n");
+        let mut archive = Archive::new_seekable(Cursor::new(input));
+        let mut symbols = archive.symbols().unwrap();
+        assert_eq!(symbols.table_len(), 3);
+        assert_eq!(symbols.next(), Some((b"foo" as &[u8], 96)));
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.table_len(), 3);
+        let rest: Vec<(&[u8], u64)> = symbols.collect();
+        assert_eq!(rest, vec![(b"bar" as &[u8], 96), (b"baz", 96)]);
+
+        // A BSD symbol whose name would start past the end of the string
+        // table gets an empty name.
+        let mut input = b"!<arch>\n".to_vec();
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "#1/12", 0, 0, 0, 0, 40
+        );
+        input.extend_from_slice(header.as_bytes());
+        input.extend_from_slice(b"__.SYMDEF\x00\x00\x00");
+        input.extend_from_slice(&16u32.to_le_bytes());
+        for &(name, offset) in [(0u32, 108u32), (100, 108)].iter() {
+            input.extend_from_slice(&name.to_le_bytes());
+            input.extend_from_slice(&offset.to_le_bytes());
+        }
+        input.extend_from_slice(&4u32.to_le_bytes());
+        input.extend_from_slice(b"foo\x00");
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "foo.o", 0, 0, 0, 644, 4
+        );
+        input.extend_from_slice(header.as_bytes());
+        input.extend_from_slice(b"foo\n");
+        let mut archive = Archive::new_seekable(Cursor::new(input));
+        let symbols: Vec<(Vec<u8>, u64)> = archive
+            .symbols()
+            .unwrap()
+            .map(|(symbol, offset)| (symbol.to_vec(), offset))
+            .collect();
+        assert_eq!(symbols, vec![(b"foo".to_vec(), 108), (Vec::new(), 108)]);
+        archive.symbols().unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn entry_offsets_match_symbol_table() {
+        let names: [&[u8]; 2] = [b"foo.o", b"this_is_a_very_long_filename.o"];
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        builder.set_auto_bsd(true);
+        for (name, symbol) in names.iter().zip(&[b"foo", b"bar"]) {
+            let header = Header::new(name.to_vec(), 3);
+            builder
+                .append_with_symbols(&header, "abc".as_bytes(), &[*symbol])
+                .unwrap();
+        }
+        let input = builder.into_inner().unwrap();
+        let mut archive = Archive::new(Cursor::new(input.as_slice()));
+        let symbols: Vec<u64> =
+            archive.symbols().unwrap().map(|(_, offset)| offset).collect();
+        let mut offsets = Vec::new();
+        while let Some(entry) = archive.next_entry() {
+            let entry = entry.unwrap();
+            let start = entry.data_offset() as usize;
+            assert_eq!(&input[start..start + 3], b"abc");
+            offsets.push(entry.member_offset());
+        }
+        assert_eq!(offsets, symbols);
+        let entry = archive.jump_to_entry(1).unwrap();
+        assert_eq!(entry.member_offset(), offsets[1]);
+        assert_eq!(entry.data_offset(), offsets[1] + 60 + 32);
+    }
+
+    #[test]
+    fn read_all_entry_data() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  10        `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.bytes_remaining(), 7);
+            let mut buffer = [0; 3];
+            entry.read_exact(&mut buffer).unwrap();
+            assert_eq!(entry.bytes_remaining(), 4);
+            assert_eq!(entry.read_all().unwrap(), b"bar\n");
+            assert_eq!(entry.bytes_remaining(), 0);
+            assert_eq!(entry.position, entry.length);
+        }
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        let error = entry.read_all().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn debug_format_header() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let entry = archive.next_entry().unwrap().unwrap();
+        let header = entry.header().clone();
+        assert_eq!(&header, entry.header());
+        assert_eq!(
+            format!("{:?}", header),
+            "Header { identifier: \"foo.txt\", mtime: 1487552916, uid: 501, \
+             gid: 20, mode: 0o100644, size: 7 }"
+        );
+    }
+
+    #[test]
+    fn header_mtime_as_system_time() {
+        let mut header = Header::new(b"foo.txt".to_vec(), 7);
+        header.set_mtime(1487552916);
+        let modified = header.modified().unwrap();
+        assert_eq!(
+            modified.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            1487552916
+        );
+        header.set_modified(modified + Duration::from_millis(1500));
+        assert_eq!(header.mtime(), 1487552917);
+        header.set_modified(UNIX_EPOCH - Duration::from_secs(60));
+        assert_eq!(header.mtime(), 0);
+    }
+
+    #[test]
+    fn set_modified_truncates_toward_epoch() {
+        let mut header = Header::new(b"foo.txt".to_vec(), 7);
+        let cases = [
+            (UNIX_EPOCH, 0),
+            (UNIX_EPOCH + Duration::from_nanos(1), 0),
+            (UNIX_EPOCH + Duration::new(0, 999_999_999), 0),
+            (UNIX_EPOCH + Duration::from_secs(1), 1),
+            (UNIX_EPOCH + Duration::new(1487552916, 999_999_999), 1487552916),
+            (UNIX_EPOCH - Duration::from_nanos(1), 0),
+            (UNIX_EPOCH - Duration::new(1, 500_000_000), 0),
+        ];
+        for &(time, mtime) in cases.iter() {
+            header.set_modified(time);
+            assert_eq!(header.mtime(), mtime);
+        }
+        let time = UNIX_EPOCH + Duration::new(1487552916, 123_456_789);
+        header.set_modified(time);
+        let modified = header.modified().unwrap();
+        assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1487552916));
+        header.set_modified(modified);
+        assert_eq!(header.mtime(), 1487552916);
+    }
+
+    #[test]
+    fn identify_special_member_headers() {
+        let symbol_tables: [&[u8]; 4] =
+            [b"/", b"/SYM64/", b"__.SYMDEF", b"__.SYMDEF SORTED"];
+        for &identifier in symbol_tables.iter() {
+            let header = Header::new(identifier.to_vec(), 0);
+            assert!(header.is_symbol_table(), "{:?}", identifier);
+            assert!(!header.is_name_table(), "{:?}", identifier);
+        }
+        let header = Header::new(b"//".to_vec(), 0);
+        assert!(header.is_name_table());
+        assert!(!header.is_symbol_table());
+        let ordinary: [&[u8]; 6] =
+            [b"foo.o", b"/0", b"/ ", b"__.SYMDEF ", b"__.symdef", b"///"];
+        for &identifier in ordinary.iter() {
+            let header = Header::new(identifier.to_vec(), 0);
+            assert!(!header.is_symbol_table(), "{:?}", identifier);
+            assert!(!header.is_name_table(), "{:?}", identifier);
+        }
+
+        let bytes =
+            *b"//                                              4         `\n";
+        let (header, kind) = Header::parse(&bytes).unwrap();
+        assert_eq!(kind, ParsedKind::Plain);
+        assert!(header.is_name_table());
+    }
+
+    #[test]
+    fn create_header_from_name() {
+        let expected = Header::new(b"foo.txt".to_vec(), 7);
+        assert_eq!(Header::with_name("foo.txt", 7), expected);
+        assert_eq!(Header::with_name(String::from("foo.txt"), 7), expected);
+        assert_eq!(Header::with_name(&b"foo.txt"[..], 7), expected);
+        assert_eq!(Header::with_name(b"foo.txt".to_vec(), 7), expected);
+
+        let path = PathBuf::from("some").join("dir").join("foo.txt");
+        assert_eq!(Header::from_path_name(&path, 7).unwrap(), expected);
+        assert_eq!(Header::from_path_name("foo.txt", 7).unwrap(), expected);
+        let error = Header::from_path_name("some/..", 7).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn split_header_mode() {
+        let mut header = Header::new(b"foo.sh".to_vec(), 0);
+        header.set_mode(0o104755);
+        assert_eq!(header.mode(), 0o104755);
+        assert_eq!(header.permissions(), 0o4755);
+        assert_eq!(header.file_type(), 0o100000);
+        header.set_mode(0o644);
+        assert_eq!(header.permissions(), 0o644);
+        assert_eq!(header.file_type(), 0);
+        header.set_mode(0o120777);
+        assert_eq!(header.file_type(), 0o120000);
+        assert_eq!(header.mode(), 0o120777);
+    }
+
+    #[test]
+    fn parse_and_serialize_raw_headers() {
+        let mut header = Header::new(b"foo.txt".to_vec(), 7);
+        header.set_mtime(1487552916);
+        header.set_uid(501);
+        header.set_gid(20);
+        header.set_mode(0o100644);
+        let bytes = header.to_bytes(Variant::Common).unwrap();
+        assert_eq!(
+            &bytes[..],
+            &b"foo.txt         1487552916  501   20    100644  7         `\n"
+                [..]
+        );
+        assert_eq!(
+            Header::parse(&bytes).unwrap(),
+            (header.clone(), ParsedKind::Plain)
+        );
+        let bytes = header.to_bytes(Variant::GNU).unwrap();
+        assert_eq!(&bytes[..8], b"foo.txt/");
+        assert_eq!(
+            Header::parse(&bytes).unwrap(),
+            (header, ParsedKind::Plain)
+        );
+
+        let header =
+            Header::new(b"this_is_a_very_long_filename.txt".to_vec(), 7);
+        let bytes = header.to_bytes(Variant::BSD).unwrap();
+        let (parsed, kind) = Header::parse(&bytes).unwrap();
+        assert_eq!(kind, ParsedKind::BsdExtended(32));
+        assert_eq!(parsed.identifier(), b"#1/32");
+        assert_eq!(parsed.size(), 39);
+        let error = header.to_bytes(Variant::GNU).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = header.to_bytes(Variant::Common).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let bytes =
+            b"/18             0           0     0     644     4         `\n";
+        let (parsed, kind) = Header::parse(bytes).unwrap();
+        assert_eq!(kind, ParsedKind::GnuNameTable(18));
+        assert_eq!(parsed.identifier(), b"/18");
+        let bytes =
+            b"//                                              20        `\n";
+        let (parsed, kind) = Header::parse(bytes).unwrap();
+        assert_eq!(kind, ParsedKind::Plain);
+        assert_eq!(parsed.identifier(), b"//");
+
+        let bytes =
+            b"foo.txt         0           0     0     644     4         xx";
+        let error = Header::parse(bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn list_names_without_seeking() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        long_filename.txt/\n\n\
+        foo.txt/        1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        /0              1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let reader = SlowReader { current_position: 0, buffer: input };
+        let mut archive = Archive::new(reader);
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+        }
+        let names = archive.names().unwrap();
+        assert_eq!(
+            names,
+            vec![b"foo.txt".to_vec(), b"long_filename.txt".to_vec()]
+        );
+        assert_eq!(archive.len().unwrap(), 2);
+        assert!(!archive.is_empty().unwrap());
+        assert!(archive.next_entry().is_none());
+
+        let mut archive = Archive::new("!<arch>\n".as_bytes());
+        assert!(archive.is_empty().unwrap());
+    }
+
+    #[test]
+    fn list_names_after_scanning() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        let names = archive.names().unwrap();
+        assert_eq!(names, vec![b"foo.txt".to_vec(), b"bar.txt".to_vec()]);
+        assert!(archive.next_entry().is_none());
+    }
+
+    fn validation_error(input: &[u8]) -> (u64, Error) {
+        let mut archive = Archive::new(Cursor::new(input));
+        match archive.validate().unwrap_err() {
+            Error::InvalidMember { offset, error } => (offset, *error),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_archive() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        long_filename.txt/\n\n\
+        foo.txt/        1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        /0              1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.validate().unwrap();
+        assert!(archive.next_entry().is_none());
+
+        let input = b"!<junk>\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        match archive.validate().unwrap_err() {
+            Error::BadMagic => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_archive_with_bad_terminator() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         ~\n\
+        baz\n";
+        // Ordinary reading doesn't check the terminator.
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        assert!(archive.next_entry().unwrap().is_ok());
+        assert!(archive.next_entry().unwrap().is_ok());
+        let (offset, error) = validation_error(input);
+        assert_eq!(offset, 76);
+        match error {
+            Error::InvalidField { field: "terminator", value } => {
+                assert_eq!(value, "~\n");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_archive_with_truncated_data() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  10        `\n\
+        baz\n";
+        let (offset, error) = validation_error(input);
+        assert_eq!(offset, 76);
+        match error {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_archive_with_bad_padding() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n*\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let (offset, error) = validation_error(input);
+        assert_eq!(offset, 75);
+        match error {
+            Error::InvalidPadding(b'*') => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_non_utf8_identifier() {
+        let input = b"\
+        !<arch>\n\
+        #1/8            0           0     0     644     12        `\n\
+        caf\xe9.txtbaz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"caf\xe9.txt");
+        assert_eq!(entry.header().identifier_str(), "caf\u{fffd}.txt");
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let path = entry.header().identifier_path();
+            assert_eq!(path.as_os_str().as_bytes(), b"caf\xe9.txt");
+            assert_eq!(path.extension().unwrap(), "txt");
+        }
+    }
+
+    #[test]
+    fn unpack_archive_to_directory() {
+        let dir = env::temp_dir()
+            .join(format!("ar-archive-unpack-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.awesome.txt 1487552919  501   20    100755  22        `\n\
+        This file is awesome!\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_mtime(true);
+        archive.unpack(&dir).unwrap();
+        assert_eq!(fs::read(dir.join("foo.txt")).unwrap(), b"foobar\n");
+        let path = dir.join("bar.awesome.txt");
+        assert_eq!(fs::read(&path).unwrap(), b"This file is awesome!\n");
+        let metadata = fs::metadata(&path).unwrap();
+        let mtime = metadata.modified().unwrap();
+        let mtime = mtime.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(mtime, 1487552919);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unpack_archive_with_progress() {
+        let dir = env::temp_dir()
+            .join(format!("ar-archive-unpack-progress-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut builder = Builder::new(Vec::new());
+        let big = vec![b'x'; 20000];
+        let header = Header::new(b"big.bin".to_vec(), big.len() as u64);
+        builder.append(&header, big.as_slice()).unwrap();
+        let header = Header::new(b"foo.txt".to_vec(), 7);
+        builder.append(&header, "foobar\n".as_bytes()).unwrap();
+        let header = Header::new(b"empty".to_vec(), 0);
+        builder.append(&header, io::empty()).unwrap();
+        let input = builder.into_inner().unwrap();
+
+        let mut calls = Vec::new();
+        let archive = Archive::new(input.as_slice());
+        archive
+            .unpack_with_progress(&dir, |header, written, total| {
+                calls.push((header.identifier().to_vec(), written, total));
+            })
+            .unwrap();
+        let expected = vec![
+            (b"big.bin".to_vec(), 0, 20000),
+            (b"big.bin".to_vec(), 8192, 20000),
+            (b"big.bin".to_vec(), 16384, 20000),
+            (b"big.bin".to_vec(), 20000, 20000),
+            (b"foo.txt".to_vec(), 0, 7),
+            (b"foo.txt".to_vec(), 7, 7),
+            (b"empty".to_vec(), 0, 0),
+        ];
+        assert_eq!(calls, expected);
+        assert_eq!(fs::read(dir.join("big.bin")).unwrap(), big);
+        assert_eq!(fs::read(dir.join("foo.txt")).unwrap(), b"foobar\n");
+        assert_eq!(fs::read(dir.join("empty")).unwrap(), b"");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unpack_without_preserving_metadata() {
+        let dir = env::temp_dir()
+            .join(format!("ar-archive-unpack-plain-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = b"\
+        !<arch>\n\
+        bar.awesome.txt 1487552919  501   20    100755  22        `\n\
+        This file is awesome!\n";
+        let archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.unpack(&dir).unwrap();
+        let path = dir.join("bar.awesome.txt");
+        assert_eq!(fs::read(&path).unwrap(), b"This file is awesome!\n");
+        let metadata = fs::metadata(&path).unwrap();
+        let mtime = metadata.modified().unwrap();
+        let mtime = mtime.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(mtime > 1487552919);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o111, 0);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unpack_rejects_path_traversal() {
+        let dir = env::temp_dir()
+            .join(format!("ar-archive-traversal-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = b"\
+        !<arch>\n\
+        //                                              12        `\n\
+        ../evil.txt/\n\
+        /0              0           0     0     644     4         `\n\
+        baz\n";
+        let archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = archive.unpack(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(!dir.join("../evil.txt").exists());
+
+        let input = b"\
+        !<arch>\n\
+        #1/11           0           0     0     644     15        `\n\
+        /etc/passwdbaz\n\n";
+        let archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = archive.unpack(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let input = b"\
+        !<arch>\n\
+        ..\\evil.txt     0           0     0     644     4         `\n\
+        baz\n";
+        let archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = archive.unpack(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpack_identifiers_with_mixed_separators() {
+        let dir = env::temp_dir()
+            .join(format!("ar-archive-separators-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = b"\
+        !<arch>\n\
+        sub\\dir/foo.txt 0           0     0     644     4         `\n\
+        baz\n";
+        let archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.unpack(&dir).unwrap();
+        let path: PathBuf = ["sub", "dir", "foo.txt"].iter().collect();
+        assert_eq!(fs::read(dir.join(path)).unwrap(), b"baz\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list_symbols_in_bsd_archive() {
+        let input = b"\
+        !<arch>\n\
+        #1/12           0           0     0     0       60        `\n\
+        __.SYMDEF\x00\x00\x00\x18\x00\x00\x00\
+        \x00\x00\x00\x00\x80\x00\x00\x00\
+        \x07\x00\x00\x00\x80\x00\x00\x00\
+        \x0b\x00\x00\x00\x80\x00\x00\x00\
+        \x10\x00\x00\x00foobar\x00baz\x00quux\x00\
+        foo.o/          1487552916  501   20    100644  16        `\n\
+        foobar,baz,quux\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        assert_eq!(archive.symbols().unwrap().len(), 3);
+        assert_eq!(archive.variant(), Variant::BSD);
+        let symbols = archive.symbols().unwrap().collect::<Vec<_>>();
+        let expected: Vec<(&[u8], u64)> =
+            vec![(b"foobar", 128), (b"baz", 128), (b"quux", 128)];
+        assert_eq!(symbols, expected);
+    }
+
+    #[test]
+    fn list_sorted_symbols_in_bsd_archive() {
+        let input = b"\
+        !<arch>\n\
+        #1/16           0           0     0     0       64        `\n\
+        __.SYMDEF SORTED\x18\x00\x00\x00\
+        \x00\x00\x00\x00\x80\x00\x00\x00\
+        \x04\x00\x00\x00\x80\x00\x00\x00\
+        \x0b\x00\x00\x00\x80\x00\x00\x00\
+        \x10\x00\x00\x00baz\x00foobar\x00quux\x00\
+        foo.o/          1487552916  501   20    100644  16        `\n\
+        foobar,baz,quux\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        assert_eq!(archive.symbols().unwrap().len(), 3);
+        assert_eq!(archive.variant(), Variant::BSD);
+        let symbols = archive.symbols().unwrap().collect::<Vec<_>>();
+        let expected: Vec<(&[u8], u64)> =
+            vec![(b"baz", 128), (b"foobar", 128), (b"quux", 128)];
+        assert_eq!(symbols, expected);
+    }
+
+    #[test]
+    fn list_symbols_in_gnu_archive() {
+        let input = b"\
+        !<arch>\n\
+        /               0           0     0     0       32        `\n\
+        \x00\x00\x00\x03\x00\x00\x00\x5c\x00\x00\x00\x5c\x00\x00\x00\x5c\
+        foobar\x00baz\x00quux\x00\
+        foo.o/          1487552916  501   20    100644  16        `\n\
+        foobar,baz,quux\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        assert_eq!(archive.symbols().unwrap().len(), 3);
+        assert_eq!(archive.variant(), Variant::GNU);
+        let symbols = archive.symbols().unwrap().collect::<Vec<_>>();
+        let expected: Vec<(&[u8], u64)> =
+            vec![(b"foobar", 92), (b"baz", 92), (b"quux", 92)];
+        assert_eq!(symbols, expected);
+    }
+
+    #[test]
+    fn list_symbols_after_reading_all_entries() {
+        let input = b"\
+        !<arch>\n\
+        /               0           0     0     0       32        `\n\
+        \x00\x00\x00\x03\x00\x00\x00\x64\x00\x00\x00\x64\x00\x00\x00\x64\
+        foobar\x00baz\x00quux\x00\
+        foo.o/          1487552916  501   20    100644  16        `\n\
+        foobar,baz,quux\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        while let Some(entry) = archive.next_entry() {
+            entry.unwrap();
+        }
+        let (symbol, offset) = archive.symbols().unwrap().next().unwrap();
+        assert_eq!(symbol, b"foobar");
+        assert_eq!(offset, 100);
+        assert_eq!(&input[offset as usize..][..6], b"foo.o/");
+    }
+
+    #[test]
+    fn non_multiple_of_two_long_ident_in_gnu_archive() {
+        let mut buffer = std::io::Cursor::new(Vec::new());
+
+        {
+            let filenames = vec![
+                b"rust.metadata.bin".to_vec(),
+                b"compiler_builtins-78891cf83a7d3547.dummy_name.rcgu.o"
+                    .to_vec(),
+            ];
+            let mut builder = GnuBuilder::new(&mut buffer, filenames.clone());
+
+            for filename in filenames {
+                builder
+                    .append(&Header::new(filename, 1), &mut (b"?" as &[u8]))
+                    .expect("add file");
+            }
+        }
+
+        buffer.set_position(0);
+
+        let mut archive = Archive::new(buffer);
+        while let Some(entry) = archive.next_entry() {
+            entry.unwrap();
+        }
+    }
+
+    /// Regression test for https://github.com/mdsteele/rust-ar/issues/22
+    #[test]
+    #[should_panic(expected = "GNU filename index out of range")]
+    fn issue_22() {
+        let data = &[
+            33, 60, 97, 114, 99, 104, 62, 10, 99, 104, 60, 159, 149, 33, 62,
+            10, 219, 87, 219, 219, 219, 96, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 48, 48, 48, 48, 48, 47, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 49, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 39, 48, 48, 48,
+            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 48, 48, 48, 51, 49, 50, 56, 48, 48, 54, 54, 54, 51, 52, 56,
+            48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48, 48,
+            48, 48, 48, 48, 48, 48, 48, 48, 47, 48, 0, 40,
+        ];
+        let mut archive = Archive::new(std::io::Cursor::new(data));
+        let _num_entries = archive.count_entries().unwrap();
+    }
+
+    #[test]
+    fn next_entry_reports_truncated_header() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  4         `\n\
+        foo\n\
+        bar.txt         1487552919  501   20";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        match error.without_context() {
+            Error::TruncatedHeader => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn next_entry_reports_truncated_data() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  10        `\n\
+        foo\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+
+        // A seekable reader reports the truncated entry itself.
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn seekable_reader_checks_size_against_length() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  9999999999`\n\
+        bar\n";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+        }
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::TruncatedData { expected: 9999999999, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+
+        // Only once the entry is reached does a streaming reader notice.
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().size(), 9999999999);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(buffer, b"bar\n");
+        }
+        assert!(archive.next_entry().unwrap().is_err());
+    }
+
+    #[test]
+    fn for_each_entry_visits_entries_in_order() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.awesome.txt 1487552919  501   20    100644  22        `\n\
+        This file is awesome!\n\
+        baz.txt         1487552920  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let mut visited = Vec::new();
+        archive
+            .for_each_entry(|mut entry| {
+                let mut start = [0; 3];
+                entry.read_exact(&mut start)?;
+                visited.push((entry.header().identifier().to_vec(), start));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            visited,
+            [
+                (b"foo.txt".to_vec(), *b"foo"),
+                (b"bar.awesome.txt".to_vec(), *b"Thi"),
+                (b"baz.txt".to_vec(), *b"baz"),
+            ]
+        );
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn for_each_entry_stops_at_first_error() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        bar\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let mut calls = 0;
+        let error = archive
+            .for_each_entry(|_| {
+                calls += 1;
+                Err(io::Error::other("stop").into())
+            })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "stop");
+        assert_eq!(calls, 1);
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"bar.txt");
+    }
+
+    #[test]
+    fn detect_variant_and_rewind() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        #1/16           1487552919  501   20    100644  26        `\n\
+        bar.awesome.txt\0This file is awesome!\n";
+        let mut reader = Cursor::new(input as &[u8]);
+        assert_eq!(Variant::detect(&mut reader).unwrap(), Variant::BSD);
+        assert_eq!(reader.position(), 0);
+
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  4         `\n\
+        foo\n";
+        let mut reader = Cursor::new(input as &[u8]);
+        assert_eq!(Variant::detect(&mut reader).unwrap(), Variant::GNU);
+        assert_eq!(reader.position(), 0);
+
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  4         `\n\
+        foo\n";
+        let mut reader = Cursor::new(input as &[u8]);
+        assert_eq!(Variant::detect(&mut reader).unwrap(), Variant::Common);
+        assert_eq!(reader.position(), 0);
+
+        let mut reader = Cursor::new(b"!<junk>\n" as &[u8]);
+        let error = Variant::detect(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn lenient_mode_tolerates_malformed_numeric_fields() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt           1487552916501   20    644\0\0\0\0\0  4x      `\n\
+        foo\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_lenient(true);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+            assert_eq!(entry.header().mtime(), 1487552916);
+            assert_eq!(entry.header().uid(), 501);
+            assert_eq!(entry.header().gid(), 20);
+            assert_eq!(entry.header().mode(), 0o644);
+            assert_eq!(entry.header().size(), 4);
+            assert_eq!(entry.read_all().unwrap(), b"foo\n");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn lenient_mode_tolerates_nul_padded_fields() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt/        -1\0\0\0\0\0\0\0\0\0\0\
+        \0\0\0\0\0\0\0\0\0\0\0\0\x00100644\x00000004\0\0\0\0`\n\
+        foo\n";
+        let mut archive = Archive::new(input as &[u8]);
+        assert!(matches!(archive.next_entry(), Some(Err(_))));
+
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_lenient(true);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+            assert_eq!(entry.header().mtime(), 0);
+            assert_eq!(entry.header().uid(), 0);
+            assert_eq!(entry.header().gid(), 0);
+            assert_eq!(entry.header().mode(), 0o100644);
+            assert_eq!(entry.header().size(), 4);
+            assert_eq!(entry.read_all().unwrap(), b"foo\n");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn lenient_mode_still_requires_digits() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  x4        `\n\
+        foo\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_lenient(true);
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::InvalidField { field: "file size", .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_entries_over_size_limit() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  9999999999`\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_size_limit(Some(1024));
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        match error.without_context() {
+            Error::SizeLimitExceeded { size: 9999999999, limit: 1024 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.set_size_limit(Some(1024));
+        let error = archive.count_entries().unwrap_err();
+        match error.without_context() {
+            Error::SizeLimitExceeded { .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // The limit can be removed again.
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_size_limit(Some(1024));
+        archive.set_size_limit(None);
+        archive.next_entry().unwrap().unwrap();
+        archive.next_entry().unwrap().unwrap();
+    }
+
+    #[test]
+    fn reject_bsd_symbol_table_lengths_over_entry_size() {
+        // Neither the symbol count nor the string table length is allocated
+        // for when it's larger than the entry itself.
+        let inputs: [&[u8]; 2] = [
+            b"\
+            !<arch>\n\
+            #1/12           0           0     0     0       20        `\n\
+            __.SYMDEF\x00\x00\x00\xf8\xff\xff\xff\x00\x00\x00\x00",
+            b"\
+            !<arch>\n\
+            #1/12           0           0     0     0       20        `\n\
+            __.SYMDEF\x00\x00\x00\x00\x00\x00\x00\xff\xff\xff\xff",
+        ];
+        for input in inputs {
+            let mut archive = Archive::new(Cursor::new(input));
+            let error = match archive.symbols() {
+                Err(error) => error,
+                Ok(_) => panic!("expected an error"),
+            };
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().contains("Symbol table needs"));
+        }
+    }
+
+    #[test]
+    fn size_limit_is_checked_before_reading_special_data() {
+        // Neither the BSD identifier nor the GNU name table is allocated or
+        // read when its declared size is over the limit.
+        let input = b"\
+        !<arch>\n\
+        #1/3000000000   0           0     0     644     3000000004`\n\
+        foo.txt\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_size_limit(Some(1024));
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::SizeLimitExceeded { size: 3000000004, limit: 1024 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let input = b"\
+        !<arch>\n\
+        //                                              2048      `\n\
+        this_is_a_long.txt/\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.set_size_limit(Some(1024));
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::SizeLimitExceeded { size: 2048, limit: 1024 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(archive.into_inner().unwrap().position(), 68);
+    }
+
+    #[test]
+    fn truncated_gnu_name_table_is_not_preallocated() {
+        let input = b"\
+        !<arch>\n\
+        //                                              9999999999`\n\
+        this_is_a_long.txt/\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(
+            error.to_string(),
+            "while reading the member at offset 8: failed to read name table"
+        );
+    }
+
+    #[test]
+    fn rewind_and_iterate_again() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n\
+        quux.txt/       1487552920  501   20    100644  5         `\n\
+        quux\n";
+        let read_all = |archive: &mut Archive<Cursor<&[u8]>>| {
+            let mut entries = Vec::new();
+            while let Some(entry) = archive.next_entry() {
+                let mut entry = entry.unwrap();
+                let identifier = entry.header().identifier().to_vec();
+                entries.push((identifier, entry.read_all().unwrap()));
+            }
+            entries
+        };
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let first = read_all(&mut archive);
+        assert_eq!(first.len(), 3);
+        assert_eq!(
+            first[0],
+            (b"this_is_a_long.txt".to_vec(), b"foobar\n".to_vec())
+        );
+        archive.rewind().unwrap();
+        assert_eq!(read_all(&mut archive), first);
+
+        // Rewind partway through, leaving part of an entry unread.
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            let mut buffer = [0; 3];
+            entry.read_exact(&mut buffer).unwrap();
+        }
+        archive.rewind().unwrap();
+        assert_eq!(read_all(&mut archive), first);
+    }
+
+    /// A 64-bit FNV-1a hash, standing in for a real digest.
+    struct Fnv(u64);
+
+    impl io::Write for Fnv {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            for &byte in buf {
+                self.0 =
+                    (self.0 ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hash_entry_data_while_reading() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  10        `\n\
+        baz\n";
+        let expected = {
+            let mut hasher = Fnv(0xcbf29ce484222325);
+            io::Write::write_all(&mut hasher, b"foobar\n").unwrap();
+            hasher.0
+        };
+        let mut archive = Archive::new(input as &[u8]);
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            let mut entry = entry.hashed(Fnv(0xcbf29ce484222325));
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+            let mut buffer = [0; 3];
+            entry.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"foo");
+            // The rest of the data is hashed too.
+            assert_eq!(entry.finalize_hash().unwrap().0, expected);
+        }
+        let entry = archive.next_entry().unwrap().unwrap();
+        let hasher = Vec::new();
+        let error = entry.hashed(hasher).finalize_hash().unwrap_err();
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn peek_header_before_reading_entry() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        {
+            let header = archive.peek_header().unwrap().unwrap();
+            assert_eq!(header.identifier(), b"this_is_a_long.txt");
+            assert_eq!(header.size(), 7);
+        }
+        assert_eq!(archive.peek_header().unwrap().unwrap().size(), 7);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"this_is_a_long.txt");
+            let mut buffer = [0; 3];
+            entry.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"foo");
+        }
+        // Peeking skips the rest of the partially-read entry.
+        assert_eq!(
+            archive.peek_header().unwrap().unwrap().identifier(),
+            b"baz.txt"
+        );
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        assert!(archive.peek_header().unwrap().is_none());
+        assert!(archive.next_entry().is_none());
+
+        // Scanning the archive after peeking doesn't lose the peeked entry.
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.peek_header().unwrap().unwrap();
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"this_is_a_long.txt");
+        assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+    }
+
+    #[test]
+    fn display_header_long() {
+        let header = Header::builder(b"baz.txt".to_vec(), 4)
+            .mode(0o100644)
+            .uid(501)
+            .gid(20)
+            .mtime(951782400 + 3723)
+            .build()
+            .unwrap();
+        assert_eq!(
+            header.display_long().to_string(),
+            "rw-r--r-- 501/20        4 Feb 29 01:02 2000 baz.txt"
+        );
+        let mut header = Header::new(b"foo".to_vec(), 12345678);
+        header.set_mode(0o104755);
+        assert_eq!(
+            header.display_long().to_string(),
+            "rwsr-xr-x 0/0 12345678 Jan  1 00:00 1970 foo"
+        );
+        header.set_mode(0o2644);
+        assert!(header.display_long().to_string().starts_with("rw-r-Sr-- "));
+        header.set_mode(0o1777);
+        assert!(header.display_long().to_string().starts_with("rwxrwxrwt "));
+    }
+
+    #[test]
+    fn read_headerless_archive() {
+        let input = b"\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new_headerless(input as &[u8]);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"this_is_a_long.txt");
+            assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"baz.txt");
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        assert!(archive.next_entry().is_none());
+        assert_eq!(archive.variant(), Variant::GNU);
+
+        let input = b"\
+        #1/16           1487552916  501   20    100644  20        `\n\
+        short\0\0\0\0\0\0\0\0\0\0\0\
+        baz\n";
+        let mut archive = Archive::new_headerless(Cursor::new(input as &[u8]));
+        assert_eq!(archive.count_entries().unwrap(), 1);
+        archive.rewind().unwrap();
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"short");
+        assert_eq!(entry.read_all().unwrap(), b"baz\n");
+    }
+
+    #[test]
+    fn read_archive_with_missing_padding_byte() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        match error.without_context() {
+            Error::InvalidPadding(b'b') => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = archive.count_entries().unwrap_err();
+        match error.without_context() {
+            Error::InvalidPadding(b'b') => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // In lenient mode, the reader resynchronizes on the next header.
+        let mut archive = Archive::new(input as &[u8]);
+        archive.set_lenient(true);
+        archive.next_entry().unwrap().unwrap();
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"bar.txt");
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        assert!(archive.next_entry().is_none());
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        archive.set_lenient(true);
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        let mut entry = archive.jump_to_entry(1).unwrap();
+        assert_eq!(entry.header().identifier(), b"bar.txt");
+        assert_eq!(entry.read_all().unwrap(), b"baz\n");
+    }
+
+    #[test]
+    fn resolve_gnu_long_names() {
+        let input = b"\
+        !<arch>\n\
+        //                                              78        `\n\
+        this_is_a_very_long_filename.txt/\n\
+        and_this_is_another_very_long_filename.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        /34             0           0     0     0       4         `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        assert!(archive.long_names().is_none());
+        archive.next_entry().unwrap().unwrap();
+        assert_eq!(
+            archive.long_names().unwrap(),
+            &b"this_is_a_very_long_filename.txt/\n\
+               and_this_is_another_very_long_filename.txt/\n"[..]
+        );
+        assert_eq!(
+            &*archive.resolve_name(b"/0              ").unwrap(),
+            b"this_is_a_very_long_filename.txt"
+        );
+        assert_eq!(
+            &*archive.resolve_name(b"/34").unwrap(),
+            b"and_this_is_another_very_long_filename.txt"
+        );
+        assert_eq!(
+            &*archive.resolve_name(b"foo.o/          ").unwrap(),
+            b"foo.o"
+        );
+        assert_eq!(&*archive.resolve_name(b"//").unwrap(), b"//");
+        match archive.resolve_name(b"/100").unwrap_err() {
+            Error::NameNotInGnuTable { index: 100 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        match archive.resolve_name(b"/x").unwrap_err() {
+            Error::InvalidField { .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    fn aix_member(next: u64, prev: u64, name: &str, data: &str) -> String {
+        let padding = if name.len() % 2 == 1 { "\0" } else { "" };
+        format!(
+            "{:<20}{:<20}{:<20}{:<12}{:<12}{:<12}{:<12}{:<4}{}{}`\n{}",
+            data.len(),
+            next,
+            prev,
+            1487552916,
+            501,
+            20,
+            644,
+            name.len(),
+            name,
+            padding,
+            data
+        )
+    }
+
+    #[test]
+    fn read_aix_big_archive() {
+        let mut input = format!(
+            "<bigaf>\n{:<20}{:<20}{:<20}{:<20}{:<20}{:<20}",
+            386, 0, 0, 128, 256, 0
+        );
+        input.push_str(&aix_member(256, 0, "foo.o", "baz\n"));
+        input.push_str("free");
+        input.push_str(&aix_member(0, 128, "bar.txt", "foobar\n"));
+        input.push_str("\nmember table");
+        let input = input.as_bytes();
+        assert_eq!(&input[252..256], b"free");
+
+        let mut archive = Archive::new(input);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.o");
+            assert_eq!(entry.header().mtime(), 1487552916);
+            assert_eq!(entry.header().uid(), 501);
+            assert_eq!(entry.header().gid(), 20);
+            assert_eq!(entry.header().mode(), 0o644);
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"bar.txt");
+            let mut buffer = [0; 3];
+            entry.read_exact(&mut buffer).unwrap();
+        }
+        assert!(archive.next_entry().is_none());
+        assert_eq!(archive.variant(), Variant::AIX);
+
+        let mut archive = Archive::new(Cursor::new(input));
+        assert_eq!(archive.count_entries().unwrap(), 2);
+        {
+            let mut entry = archive.jump_to_entry(1).unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+        }
+        archive.rewind().unwrap();
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.o");
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        assert_eq!(archive.names().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn read_empty_aix_big_archive() {
+        let input = format!(
+            "<bigaf>\n{:<20}{:<20}{:<20}{:<20}{:<20}{:<20}",
+            0, 0, 0, 0, 0, 0
+        );
+        let mut archive = Archive::new(input.as_bytes());
+        assert!(archive.next_entry().is_none());
+        assert_eq!(archive.variant(), Variant::AIX);
+    }
+
+    #[test]
+    fn entry_position() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.position(), 0);
+        let mut buffer = [0; 3];
+        entry.read_exact(&mut buffer).unwrap();
+        assert_eq!(entry.position(), 3);
+        assert_eq!(entry.position() + entry.bytes_remaining(), 7);
+        entry.read_all().unwrap();
+        assert_eq!(entry.position(), 7);
+        drop(entry);
+
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        entry.seek(SeekFrom::Start(5)).unwrap();
+        assert_eq!(entry.position(), 5);
+        assert_eq!(entry.stream_position().unwrap(), 5);
+    }
+
+    #[test]
+    fn read_all_headers() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let headers = archive.headers().unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].identifier(), b"this_is_a_long.txt");
+        assert_eq!(headers[0].mtime(), 1487552916);
+        assert_eq!(headers[1].identifier(), b"baz.txt");
+        assert_eq!(headers[1].size(), 4);
+        assert!(archive.next_entry().is_none());
+        let mut entry = archive.jump_to_entry(1).unwrap();
+        assert_eq!(entry.read_all().unwrap(), b"baz\n");
+    }
+
+    #[test]
+    fn extract_named_entry_to_writer() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n\
+        qux.txt         1487552919  501   20    100644  4         `\n\
+        qux\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let mut output = Vec::new();
+        assert!(archive.extract_to(b"bar.txt", &mut output).unwrap());
+        assert_eq!(output, b"baz\n");
+        let error = archive.extract_to(b"foo.txt", &mut output).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(!archive.extract_to(b"nope.txt", &mut output).unwrap());
+        assert!(archive.next_entry().is_none());
+
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        let mut output = Vec::new();
+        assert!(archive.extract_to(b"qux.txt", &mut output).unwrap());
+        assert_eq!(output, b"qux\n");
+        output.clear();
+        assert!(archive.extract_to(b"foo.txt", &mut output).unwrap());
+        assert_eq!(output, b"foobar\n");
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"bar.txt");
+    }
+
+    #[test]
+    fn extract_passed_entry_with_later_duplicate() {
+        // Without seeking, an entry that has been passed is found again if
+        // there is another one with the same identifier further on.
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        baz\n\
+        foo.txt         1487552919  501   20    100644  4         `\n\
+        qux\n";
+        let mut archive = Archive::new(input as &[u8]);
+        let mut output = Vec::new();
+        assert!(archive.extract_to(b"bar.txt", &mut output).unwrap());
+        output.clear();
+        assert!(archive.extract_to(b"foo.txt", &mut output).unwrap());
+        assert_eq!(output, b"qux\n");
+        let error = archive.extract_to(b"foo.txt", &mut output).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn validate_symbol_table_offsets() {
+        let input = b"\
+        !<arch>\n\
+        #1/12           0           0     0     0       60        `\n\
+        __.SYMDEF\x00\x00\x00\x18\x00\x00\x00\
+        \x00\x00\x00\x00\x80\x00\x00\x00\
+        \x07\x00\x00\x00\x80\x00\x00\x00\
+        \x0b\x00\x00\x00\x82\x00\x00\x00\
+        \x10\x00\x00\x00foobar\x00baz\x00quux\x00\
+        foo.o/          1487552916  501   20    100644  16        `\n\
+        foobar,baz,quux\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let error = archive.symbols().unwrap().validate().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("\"quux\""));
+        assert!(error.to_string().contains("130"));
+
+        let mut input = input.to_vec();
+        input[104] = 0x80;
+        let mut archive = Archive::new(Cursor::new(input));
+        archive.symbols().unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn index_archive_entries() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(Cursor::new(input as &[u8]));
+        let index = archive.index().unwrap();
+        let expected = vec![
+            (b"this_is_a_long.txt".to_vec(), 88, 7),
+            (b"baz.txt".to_vec(), 156, 4),
+        ];
+        assert_eq!(index, expected);
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"this_is_a_long.txt");
+        assert_eq!(entry.member_offset(), 88);
+    }
+
+    #[test]
+    fn debug_archive_state() {
+        let input = b"\
+        !<arch>\n\
+        //                                              20        `\n\
+        this_is_a_long.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        1487552919  501   20    100644  4         `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        assert_eq!(
+            format!("{:?}", archive),
+            "Archive { variant: None, offset: 8, entries_read: 0, .. }"
+        );
+        archive.next_entry().unwrap().unwrap();
+        assert_eq!(
+            format!("{:?}", archive),
+            "Archive { variant: Some(GNU), offset: 156, entries_read: 1, .. }"
+        );
+        archive.next_entry().unwrap().unwrap();
+        assert!(archive.next_entry().is_none());
+        assert_eq!(
+            format!("{:?}", archive),
+            "Archive { variant: Some(GNU), offset: 220, entries_read: 2, .. }"
+        );
+    }
+
+    #[cfg(feature = "deb")]
+    #[test]
+    fn find_deb_members() {
+        use crate::DebMember;
+
+        let input = b"\
+        !<arch>\n\
+        debian-binary   1487552916  0     0     100644  4         `\n\
+        2.0\n\
+        control.tar.zst 1487552916  0     0     100644  3         `\n\
+        ctl\n\
+        data.tar        1487552916  0     0     100644  4         `\n\
+        data";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        {
+            let mut entry =
+                archive.deb_member(DebMember::Data).unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"data.tar");
+            assert_eq!(entry.read_all().unwrap(), b"data");
+        }
+        {
+            let mut entry =
+                archive.deb_member(DebMember::Binary).unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"2.0\n");
+        }
+        {
+            let mut entry =
+                archive.deb_member(DebMember::Control).unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"control.tar.zst");
+            assert_eq!(entry.read_all().unwrap(), b"ctl");
+        }
+
+        assert!(DebMember::Control.matches(b"control.tar.gz"));
+        assert!(DebMember::Data.matches(b"data.tar.xz"));
+        assert!(!DebMember::Data.matches(b"data.tar.7z"));
+        assert!(!DebMember::Data.matches(b"data.tarball"));
+        assert!(!DebMember::Binary.matches(b"debian-binary.gz"));
+
+        let input = b"!<arch>\n";
+        let mut archive = Archive::new_seekable(Cursor::new(input as &[u8]));
+        assert!(archive.deb_member(DebMember::Data).unwrap().is_none());
+    }
+
+    #[test]
+    fn seek_entry_forward_without_seek() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  10        `\n\
+        0123456789\
+        bar.txt         1487552916  501   20    100644  10        `\n\
+        0123";
+        let mut archive = Archive::new(input as &[u8]);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.seek_forward(3).unwrap(), 3);
+            let mut buffer = [0; 2];
+            entry.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"34");
+            assert_eq!(entry.seek_forward(0).unwrap(), 5);
+            let error = entry.seek_forward(6).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            assert_eq!(entry.position(), 5);
+            assert_eq!(entry.seek_forward(5).unwrap(), 10);
+            assert_eq!(entry.read_all().unwrap(), b"");
+        }
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        let error = entry.seek_forward(8).unwrap_err();
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skip_entries_after_peeking() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  4         `\n\
+        bar\n\
+        baz.txt         1487552920  501   20    100644  10        `\n\
+        baz\n";
+        let mut archive = Archive::new(input as &[u8]);
+        assert_eq!(archive.peek_header().unwrap().unwrap().size(), 7);
+        assert!(archive.skip_entry().unwrap());
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"bar.txt");
+            assert_eq!(entry.read_all().unwrap(), b"bar\n");
+        }
+        let error = archive.skip_entry().unwrap_err();
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(archive.next_entry().is_none());
+
+        let mut archive = Archive::new_seekable(Cursor::new(&input[..140]));
+        assert!(archive.skip_entry().unwrap());
+        assert!(archive.skip_entry().unwrap());
+        assert!(!archive.skip_entry().unwrap());
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn public_magic_strings() {
+        assert_eq!(crate::COMMON_MAGIC, b"!<arch>\n");
+        assert_eq!(crate::THIN_MAGIC, b"!<thin>\n");
+        assert_eq!(crate::AIX_BIG_MAGIC, b"<bigaf>\n");
+        let mut archive = Archive::new(&crate::COMMON_MAGIC[..]);
+        assert!(archive.next_entry().is_none());
+        assert!(!archive.is_thin());
+        let mut archive = Archive::new(&crate::THIN_MAGIC[..]);
+        assert!(archive.next_entry().is_none());
+        assert!(archive.is_thin());
+    }
+
+    #[test]
+    fn next_entry_errors_name_the_member() {
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  8         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  10        `\n\
+        bar\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(
+            error.to_string(),
+            "while reading member \"bar.txt\" at offset 76: unexpected EOF \
+             in the middle of archive entry data (4 of 10 bytes present)"
+        );
+        match error {
+            Error::InMember {
+                action: "reading",
+                identifier: Some(ref identifier),
+                offset: Some(76),
+                ref error,
+            } if identifier == b"bar.txt" => match **error {
+                Error::TruncatedData { expected: 10, actual: 4 } => {}
+                ref other => panic!("unexpected error: {:?}", other),
+            },
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+        match error.without_context() {
+            Error::TruncatedData { expected: 10, actual: 4 } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Before the header is parsed, only the offset is known.
+        let input = b"\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  8         `\n\
+        foobar\n\n\
+        bar.txt         1487552919  501   20    100644  1x        `\n\
+        bar\n";
+        let mut archive = Archive::new(input as &[u8]);
+        archive.next_entry().unwrap().unwrap();
+        let error = match archive.next_entry() {
+            Some(Err(error)) => error,
+            _ => panic!("expected an error"),
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error
+            .to_string()
+            .starts_with("while reading the member at offset 76: "));
+        match error.without_context() {
+            Error::InvalidField { field: "file size", .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
     }
 }
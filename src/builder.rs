@@ -1,60 +1,465 @@
-use std::collections::{HashMap, HashSet};
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{self, Error, ErrorKind, Read, Result, Write};
-use std::path::Path;
+use std::cmp;
+use std::collections::{hash_map, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{
+    self, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write,
+};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::archive::{GLOBAL_HEADER, GNU_NAME_TABLE_ID};
-use crate::header::Header;
+use crate::archive::{
+    Archive, Variant, BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID,
+    BSD_SYMBOL_LOOKUP_TABLE_ID, COMMON_MAGIC, GLOBAL_HEADER_LEN,
+    GNU_NAME_TABLE_ID, THIN_MAGIC,
+};
+use crate::entry::Entry;
+use crate::error::{member_context, Error as ArError, Result};
+use crate::header::{
+    file_name_identifier, osstr_to_bytes, Header, ENTRY_HEADER_LEN,
+};
 
-#[cfg(unix)]
-use std::os::unix::ffi::OsStrExt;
+// ========================================================================= //
+
+// Defines the methods that `Builder` and `GnuBuilder` share, for either of
+// them to use in its `impl` block.  Each builder provides the state they
+// work with (its `output` and `options` fields) and a `write_buffered()`
+// method that writes out anything it holds back until the archive is
+// finished.
+macro_rules! shared_builder_methods {
+    () => {
+        /// Finishes writing the archive, writing out any entries and tables
+        /// that have been buffered, and then flushes the underlying writer.
+        /// No more entries can be appended afterwards.
+        ///
+        /// Calling this before `into_inner()` isn't required, since
+        /// `into_inner()` finishes the archive itself, but it lets errors from
+        /// the final writes be handled without giving up the builder.
+        /// Dropping a builder without calling either method may leave the
+        /// archive incomplete.
+        ///
+        /// If writing the buffered data fails, the archive can't be completed,
+        /// and later calls return the same error.  Only a failed flush may be
+        /// retried.
+        pub fn finish(&mut self) -> Result<()> {
+            if self.output.check_finishing()? {
+                let result = self.write_buffered();
+                self.output.end_writing(result)?;
+            }
+            self.output.writer.flush().map_err(ArError::from)
+        }
+
+        /// Unwrap this archive builder, returning the underlying writer
+        /// object.  This calls `finish()` first if it hasn't been called
+        /// already.
+        pub fn into_inner(mut self) -> Result<W> {
+            self.finish()?;
+            Ok(self.output.writer.inner)
+        }
+
+        /// Sets whether this builder produces a deterministic archive.  When
+        /// enabled, every appended entry is written with its mtime, owner ID,
+        /// and group ID set to zero and its mode set to `0o644`, regardless of
+        /// the `Header` passed in; identifiers and data are kept as-is.  This
+        /// is equivalent to the `D` modifier of the `ar` command line utility,
+        /// and is disabled by default.
+        pub fn set_deterministic(&mut self, deterministic: bool) {
+            self.options.deterministic = deterministic;
+        }
+
+        /// Sets whether an owner or group ID too large for its six-digit field
+        /// in the entry header (i.e. over 999999, as is common for users from
+        /// a directory service) is written as 999999 instead.  By default,
+        /// such an ID makes `append()` return an error carrying
+        /// `ar::Error::FieldOverflow`, since the archive can't record it; this
+        /// is for when ownership doesn't matter.  Clamped IDs are not
+        /// reported.
+        pub fn set_clamp_ids(&mut self, clamp: bool) {
+            self.options.clamp_ids = clamp;
+        }
+
+        /// Sets the largest amount of data that `append_unknown_size()`
+        /// buffers in memory; an entry with more data than this is buffered in
+        /// a temporary file instead.  Likewise, entries buffered until
+        /// `finish()` are moved to a temporary file once there are more than
+        /// this many bytes of them.  The default is 1 MiB.
+        pub fn set_spill_threshold(&mut self, threshold: usize) {
+            self.options.spill_threshold = threshold;
+        }
+
+        /// Sets the byte written after the data of an entry with an odd amount
+        /// of data, to keep the next entry header at an even offset.  The `ar`
+        /// format calls for a newline, which is the default, but some tools
+        /// write (or expect) a NUL byte instead.  `Archive` accepts either.
+        pub fn set_pad_byte(&mut self, pad_byte: u8) {
+            self.options.pad_byte = pad_byte;
+        }
+
+        /// Sets whether this builder refuses entries whose identifier is the
+        /// same as that of an entry already appended.  The `ar` format has no
+        /// way for one member to refer to another's data, so rather than
+        /// silently writing a second member that shadows (or is shadowed by)
+        /// the first, appending a duplicate fails with
+        /// `ar::Error::DuplicateIdentifier` and nothing is written.  This is
+        /// disabled by default.
+        pub fn set_dedup(&mut self, dedup: bool) {
+            if !dedup {
+                self.options.seen_identifiers = None;
+            } else if self.options.seen_identifiers.is_none() {
+                self.options.seen_identifiers = Some(HashSet::new());
+            }
+        }
+
+        /// Sets whether `append_path()` and `append_path_with_name()` follow a
+        /// symbolic link, adding the contents of the file it points to, as the
+        /// `ar` command line utility does.  When disabled, passing either
+        /// method the path of a symbolic link is an error.  Either way, a
+        /// symbolic link is never stored as such, since the archive format has
+        /// no kind of entry for one.  This is enabled by default.
+        pub fn set_follow_symlinks(&mut self, follow: bool) {
+            self.options.follow_symlinks = follow;
+        }
+    };
+}
+
+// ========================================================================= //
 
 /// A structure for building Common or BSD-variant archives (the archive format
 /// typically used on e.g. BSD and Mac OS X systems).
 ///
 /// This structure has methods for building up an archive from scratch into any
-/// arbitrary writer.
+/// arbitrary writer.  Identifiers that need the BSD variant's encoding are
+/// only accepted once it has been enabled with `set_auto_bsd()`.
 pub struct Builder<W: Write> {
-    writer: W,
-    started: bool,
+    output: Output<W>,
+    options: Options,
+    symbol_table: Option<SymbolTableBuffer>,
+    auto_bsd: bool,
+    sort_symbols: bool,
 }
 
 impl<W: Write> Builder<W> {
     /// Create a new archive builder with the underlying writer object as the
     /// destination of all data written.
     pub fn new(writer: W) -> Builder<W> {
-        Builder { writer, started: false }
+        Builder {
+            output: Output::new(writer),
+            options: Options::new(),
+            symbol_table: None,
+            auto_bsd: false,
+            sort_symbols: false,
+        }
+    }
+
+    /// Create a new archive builder that will write a BSD symbol table (the
+    /// `__.SYMDEF` member) in front of all other entries.  Use
+    /// `append_with_symbols()` to record which symbols each entry defines.
+    ///
+    /// Because the symbol table must precede the entries it refers to, and
+    /// its size isn't known until every entry has been added, all entries are
+    /// buffered (in memory, or in a temporary file once they are larger than
+    /// the threshold set by `set_spill_threshold()`) and nothing is written
+    /// to the underlying writer until `finish()` (or `into_inner()`) is
+    /// called.
+    pub fn new_with_symbol_table(writer: W) -> Builder<W> {
+        let mut builder = Builder::new(writer);
+        builder.symbol_table = Some(SymbolTableBuffer::new());
+        builder
+    }
+
+    shared_builder_methods!();
+
+    /// Writes out the buffered entries and symbol table, if there are any.
+    fn write_buffered(&mut self) -> io::Result<()> {
+        if let Some(mut table) = self.symbol_table.take() {
+            self.output.writer.write_all(COMMON_MAGIC)?;
+            let identifier = if self.sort_symbols {
+                // A stable sort, so that the first member listed for a
+                // symbol defined more than once stays first.
+                table.symbols.sort_by(|a, b| a.0.cmp(&b.0));
+                BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID
+            } else {
+                BSD_SYMBOL_LOOKUP_TABLE_ID
+            };
+            let members_start = (GLOBAL_HEADER_LEN + ENTRY_HEADER_LEN) as u64
+                + bsd_symbol_table_size(&table.symbols, identifier);
+            write_bsd_symbol_table(
+                &mut self.output.writer,
+                &table.symbols,
+                members_start,
+                identifier,
+            )?;
+            table.members.copy_to(&mut self.output.writer)?;
+        } else if !self.output.started {
+            // Even an archive with no entries needs its global header.
+            self.output.writer.write_all(COMMON_MAGIC)?;
+            self.output.started = true;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes written to the underlying writer so far,
+    /// including the global header and every entry's header (with any
+    /// extended identifier), data, and padding byte.  Since a builder that
+    /// writes a symbol table buffers all entries until `finish()`, this stays
+    /// at zero for one until then.
+    pub fn bytes_written(&self) -> u64 {
+        self.output.writer.count
+    }
+
+    /// Sets whether identifiers that the Common variant can't represent
+    /// (those longer than 16 bytes, or containing a space) are written using
+    /// the BSD variant's `#1/len` encoding, which stores the identifier at
+    /// the start of the entry's data.  This is disabled by default, in which
+    /// case appending an entry with such an identifier is an error, so that
+    /// the archive is guaranteed to be a Common one.  When enabled, the
+    /// archive written is a Common one unless such an identifier is
+    /// appended, and a mix of Common and BSD-style headers otherwise; BSD
+    /// tools read all of them, but GNU tools will show the extended
+    /// identifiers as part of the data.
+    pub fn set_auto_bsd(&mut self, auto_bsd: bool) {
+        self.auto_bsd = auto_bsd;
+    }
+
+    /// Sets whether the symbol table written by a builder created with
+    /// `Builder::new_with_symbol_table()` has its symbols sorted by name, so
+    /// that a linker can binary search it.  Such a table is written as a
+    /// `__.SYMDEF SORTED` member, as `ranlib` does on macOS, rather than as
+    /// a `__.SYMDEF` member.  This is disabled by default.
+    pub fn set_sort_symbols(&mut self, sort: bool) {
+        self.sort_symbols = sort;
+    }
+
+    /// Adds a new entry to this archive.  Returns an error if the entry's
+    /// identifier is empty or contains a newline, or if it needs the BSD
+    /// encoding and that hasn't been enabled with `set_auto_bsd()`.
+    pub fn append<R: Read>(&mut self, header: &Header, data: R) -> Result<()> {
+        self.output.check_not_finished()?;
+        check_identifier(header.identifier())?;
+        let identifier = header.identifier();
+        if !self.auto_bsd
+            && (identifier.len() > 16 || identifier.contains(&b' '))
+        {
+            let msg = format!(
+                "Identifier {:?} is too long or contains a space, so it \
+                 can't be written in a Common-variant archive",
+                String::from_utf8_lossy(identifier)
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        self.options.check_duplicate(header.identifier())?;
+        let adjusted_header;
+        let header = if self.options.deterministic {
+            adjusted_header = header.to_deterministic();
+            &adjusted_header
+        } else if self.options.clamp_ids {
+            adjusted_header = header.to_clamped_ids();
+            &adjusted_header
+        } else {
+            header
+        };
+        let pad_byte = self.options.pad_byte;
+        let identifier = Some(header.identifier());
+        let threshold = self.options.spill_threshold;
+        if let Some(ref mut table) = self.symbol_table {
+            // The member's offset isn't known until the table is written.
+            table
+                .append(threshold, |writer| {
+                    header.write(writer)?;
+                    write_data(writer, header, data, pad_byte)
+                })
+                .map_err(|error| {
+                    member_context(error, "writing", identifier, None)
+                })?;
+            self.options.record_identifier(header.identifier());
+            self.output.members += 1;
+            return Ok(());
+        }
+        if !self.output.started {
+            self.output.writer.write_all(COMMON_MAGIC)?;
+            self.output.started = true;
+        }
+        let offset = Some(self.output.writer.count);
+        header
+            .write(&mut self.output.writer)
+            .and_then(|()| {
+                write_data(&mut self.output.writer, header, data, pad_byte)
+            })
+            .map_err(|error| {
+                member_context(error, "writing", identifier, offset)
+            })?;
+        self.options.record_identifier(header.identifier());
+        self.output.members += 1;
+        Ok(())
     }
 
-    /// Unwrap this archive builder, returning the underlying writer object.
-    pub fn into_inner(self) -> Result<W> {
-        Ok(self.writer)
+    /// Adds each of the given entries to this archive in turn, as with
+    /// `append()`, stopping at the first error.
+    pub fn append_all<I, R>(&mut self, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Header, R)>,
+        R: Read,
+    {
+        for (header, data) in entries {
+            self.append(&header, data)?;
+        }
+        Ok(())
     }
 
-    /// Adds a new entry to this archive.
-    pub fn append<R: Read>(
+    /// Adds a new entry to this archive, whose data is read from `data`
+    /// until it runs out, rather than being `header.size()` bytes long.  The
+    /// header's size field is ignored, and replaced with the actual size.
+    ///
+    /// Since the size must be written before the data, the data is first
+    /// read into a buffer, which is kept in memory if it is no larger than
+    /// the threshold set by `set_spill_threshold()`, and otherwise written
+    /// to a temporary file.  Either way, the data is copied one more time
+    /// than by `append()`, which should be preferred when the size is known.
+    pub fn append_unknown_size<R: Read>(
         &mut self,
         header: &Header,
-        mut data: R,
+        data: R,
     ) -> Result<()> {
-        if !self.started {
-            self.writer.write_all(GLOBAL_HEADER)?;
-            self.started = true;
-        }
-        header.write(&mut self.writer)?;
-        let actual_size = io::copy(&mut data, &mut self.writer)?;
-        if actual_size != header.size() {
-            let msg = format!(
-                "Wrong file size (header.size() = {}, actual \
-                               size was {})",
+        self.output.check_not_finished()?;
+        let (size, mut spooled) =
+            spool_data(data, self.options.spill_threshold)?;
+        let mut header = header.clone();
+        header.set_size(size);
+        self.append(&header, &mut spooled)
+    }
+
+    /// Adds a new entry to this archive, like `append()`, except that all of
+    /// the data is read (and its size checked) before anything is written.
+    /// If reading the data fails, or it turns out to be shorter or longer
+    /// than `header.size()`, an error is returned and the archive is left as
+    /// it was, so more entries can still be appended; with `append()`, the
+    /// archive would be left with a partially written entry.  (An error from
+    /// the underlying writer itself can still leave a partial entry.)
+    ///
+    /// The data is buffered as by `append_unknown_size()`, in memory or, if
+    /// larger than the threshold set by `set_spill_threshold()`, in a
+    /// temporary file.
+    pub fn append_atomic<R: Read>(
+        &mut self,
+        header: &Header,
+        data: R,
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let data = data.take(header.size().saturating_add(1));
+        let (size, mut spooled) =
+            spool_data(data, self.options.spill_threshold)?;
+        if size != header.size() {
+            return Err(wrong_size_error(
+                header.identifier(),
                 header.size(),
-                actual_size
-            );
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+                size,
+            )
+            .into());
+        }
+        self.append(header, &mut spooled)
+    }
+
+    /// Adds an entry whose 60-byte header has already been encoded, such as
+    /// one copied verbatim from another archive of the variant being
+    /// written, followed by exactly `size` bytes of `data` and, if `size` is
+    /// odd, a padding byte.  Unlike `append()`, this doesn't parse, check,
+    /// or re-encode the header at all (so `set_deterministic()` and
+    /// `set_dedup()` have no effect on it), which makes it cheaper than
+    /// `append_archive()` for copying members.
+    ///
+    /// The caller is responsible for the header being correct: its size
+    /// field must match `size` (which, for a BSD extended identifier,
+    /// includes the identifier stored at the start of the data), and its
+    /// identifier must be encoded as readers of this archive will expect.
+    /// Otherwise the archive written will be corrupt.
+    pub fn append_raw<R: Read>(
+        &mut self,
+        header_bytes: &[u8; ENTRY_HEADER_LEN],
+        data: R,
+        size: u64,
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let identifier = raw_identifier(header_bytes);
+        let pad_byte = self.options.pad_byte;
+        let threshold = self.options.spill_threshold;
+        if let Some(ref mut table) = self.symbol_table {
+            table
+                .append(threshold, |writer| {
+                    writer.write_all(header_bytes)?;
+                    copy_data(writer, identifier, size, data, pad_byte)
+                })
+                .map_err(|error| {
+                    member_context(error, "writing", Some(identifier), None)
+                })?;
+            self.output.members += 1;
+            return Ok(());
+        }
+        if !self.output.started {
+            self.output.writer.write_all(COMMON_MAGIC)?;
+            self.output.started = true;
+        }
+        let offset = Some(self.output.writer.count);
+        self.output
+            .writer
+            .write_all(header_bytes)
+            .and_then(|()| {
+                copy_data(
+                    &mut self.output.writer,
+                    identifier,
+                    size,
+                    data,
+                    pad_byte,
+                )
+            })
+            .map_err(|error| {
+                member_context(error, "writing", Some(identifier), offset)
+            })?;
+        self.output.members += 1;
+        Ok(())
+    }
+
+    /// Copies every remaining entry (header and data) of an existing archive
+    /// into this archive, so that several archives can be combined without
+    /// extracting them.  Long filenames are re-encoded as needed.  Special
+    /// entries of the source archive, such as its symbol table, are not
+    /// copied.  Thin archives can't be copied, since they don't contain their
+    /// members' data.
+    pub fn append_archive<R: Read>(
+        &mut self,
+        archive: &mut Archive<R>,
+    ) -> Result<()> {
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry?;
+            check_not_thin(&entry)?;
+            let header = entry.header().clone();
+            self.append(&header, &mut entry)?;
         }
-        if actual_size % 2 != 0 {
-            self.writer.write_all(&[b'\n'])?;
+        Ok(())
+    }
+
+    /// Adds a new entry to this archive, recording that it defines the given
+    /// symbols.  This builder must have been created with
+    /// `Builder::new_with_symbol_table()`.
+    pub fn append_with_symbols<R: Read>(
+        &mut self,
+        header: &Header,
+        data: R,
+        symbols: &[&[u8]],
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let offset = match self.symbol_table {
+            Some(ref table) => table.members.len(),
+            None => {
+                let msg = "Builder was not created with \
+                           Builder::new_with_symbol_table()";
+                return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+            }
+        };
+        self.append(header, data)?;
+        if let Some(ref mut table) = self.symbol_table {
+            table.add_symbols(symbols, offset);
         }
         Ok(())
     }
@@ -62,20 +467,70 @@ impl<W: Write> Builder<W> {
     /// Adds a file on the local filesystem to this archive, using the file
     /// name as its identifier.
     pub fn append_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let name: &OsStr = path.as_ref().file_name().ok_or_else(|| {
-            let msg = "Given path doesn't have a file name";
-            Error::new(ErrorKind::InvalidInput, msg)
-        })?;
-        let identifier = osstr_to_bytes(name)?;
-        let mut file = File::open(&path)?;
+        let identifier = file_name_identifier(path.as_ref())?;
+        let mut file = open_path(path.as_ref(), self.options.follow_symlinks)?;
         self.append_file_id(identifier, &mut file)
     }
 
+    /// Adds a file on the local filesystem to this archive, using the given
+    /// name as its identifier rather than the file name from the path.
+    pub fn append_path_with_name<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: &[u8],
+    ) -> Result<()> {
+        check_identifier(name)?;
+        let mut file = open_path(path.as_ref(), self.options.follow_symlinks)?;
+        self.append_file_id(name.to_vec(), &mut file)
+    }
+
     /// Adds a file to this archive, with the given name as its identifier.
     pub fn append_file(&mut self, name: &[u8], file: &mut File) -> Result<()> {
         self.append_file_id(name.to_vec(), file)
     }
 
+    /// Adds every regular file in the directory tree rooted at `base` to
+    /// this archive.  Since archives are flat, each file's identifier is its
+    /// path relative to `base`, with components joined by `/` (e.g.
+    /// `sub/dir/foo.o`).
+    ///
+    /// Subdirectories are descended into, but aren't themselves added.
+    /// Symbolic links (whether to files or to directories) and other special
+    /// files are skipped, so a tree containing symlink loops can't cause an
+    /// infinite walk.  The tree is walked one directory at a time, with only
+    /// an open directory handle kept per level of nesting, so memory use
+    /// doesn't grow with the number of files; the order of entries within a
+    /// directory is whatever order the filesystem returns them in.
+    pub fn append_dir_all<P: AsRef<Path>>(&mut self, base: P) -> Result<()> {
+        let mut stack = vec![(fs::read_dir(base)?, Vec::new())];
+        loop {
+            let next = match stack.last_mut() {
+                Some(&mut (ref mut dir, _)) => dir.next(),
+                None => return Ok(()),
+            };
+            let entry = match next {
+                Some(entry) => entry?,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let mut identifier = stack.last().unwrap().1.clone();
+            if !identifier.is_empty() {
+                identifier.push(b'/');
+            }
+            identifier.extend(osstr_to_bytes(&entry.file_name())?);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                stack.push((fs::read_dir(entry.path())?, identifier));
+            } else if file_type.is_file() {
+                check_identifier(&identifier)?;
+                let mut file = File::open(entry.path())?;
+                self.append_file_id(identifier, &mut file)?;
+            }
+        }
+    }
+
     fn append_file_id(&mut self, id: Vec<u8>, file: &mut File) -> Result<()> {
         let metadata = file.metadata()?;
         let header = Header::from_metadata(id, &metadata);
@@ -83,6 +538,47 @@ impl<W: Write> Builder<W> {
     }
 }
 
+impl Builder<File> {
+    /// Creates a file at the given path (truncating it if it already exists)
+    /// and returns a builder that writes an archive to it.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Builder<File>> {
+        Ok(Builder::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Builder<BufWriter<W>> {
+    /// Create a new archive builder that wraps the given writer in a
+    /// `BufWriter`, so that the many small writes made for entry headers and
+    /// data are combined into fewer, larger writes to the underlying writer.
+    /// This is worthwhile when writing many small entries to e.g. a `File`.
+    ///
+    /// `finish()` flushes the buffer through to the underlying writer; use
+    /// `into_unbuffered()` to get the underlying writer back.
+    pub fn buffered(writer: W) -> Builder<BufWriter<W>> {
+        Builder::new(BufWriter::with_capacity(BUFFER_SIZE, writer))
+    }
+
+    /// Unwrap this archive builder, returning the writer inside its
+    /// `BufWriter`.  This calls `finish()` first if it hasn't been called
+    /// already.
+    pub fn into_unbuffered(self) -> Result<W> {
+        self.into_inner()?
+            .into_inner()
+            .map_err(|error| error.into_error().into())
+    }
+}
+
+impl<W: Write> fmt::Debug for Builder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("started", &self.output.started)
+            .field("finished", &self.output.finished)
+            .field("members", &self.output.members)
+            .field("bytes_written", &self.output.writer.count)
+            .finish_non_exhaustive()
+    }
+}
+
 // ========================================================================= //
 
 /// A structure for building GNU-variant archives (the archive format typically
@@ -91,12 +587,29 @@ impl<W: Write> Builder<W> {
 /// This structure has methods for building up an archive from scratch into any
 /// arbitrary writer.
 pub struct GnuBuilder<W: Write> {
-    writer: W,
+    output: Output<W>,
+    options: Options,
     short_names: HashSet<Vec<u8>>,
     long_names: HashMap<Vec<u8>, usize>,
     name_table_size: usize,
     name_table_needs_padding: bool,
-    started: bool,
+    symbol_table: Option<SymbolTableBuffer>,
+    incremental: bool,
+    thin: bool,
+}
+
+impl GnuBuilder<File> {
+    /// Creates a file at the given path (truncating it if it already exists)
+    /// and returns a builder that writes an archive to it.  As with
+    /// `GnuBuilder::new()`, the `identifiers` parameter must give the
+    /// complete list of entry identifiers that will be included in this
+    /// archive.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        identifiers: Vec<Vec<u8>>,
+    ) -> Result<GnuBuilder<File>> {
+        Ok(GnuBuilder::new(File::create(path)?, identifiers))
+    }
 }
 
 impl<W: Write> GnuBuilder<W> {
@@ -105,114 +618,488 @@ impl<W: Write> GnuBuilder<W> {
     /// the complete list of entry identifiers that will be included in this
     /// archive.
     pub fn new(writer: W, identifiers: Vec<Vec<u8>>) -> GnuBuilder<W> {
+        GnuBuilder::with_identifiers(writer, identifiers, false)
+    }
+
+    /// Create a new archive builder that will write a GNU thin archive.  A
+    /// thin archive starts with `!<thin>\n` rather than `!<arch>\n`, and
+    /// records only the header of each member; the member data stays in the
+    /// external file named by the member's identifier, which is a path
+    /// relative to the directory containing the archive.  Use
+    /// `append_thin_member()` to add members by path.
+    ///
+    /// As with `GnuBuilder::new()`, the `identifiers` parameter must give the
+    /// complete list of entry identifiers (here, member paths) that will be
+    /// included in this archive.
+    pub fn new_thin(writer: W, identifiers: Vec<Vec<u8>>) -> GnuBuilder<W> {
+        GnuBuilder::with_identifiers(writer, identifiers, true)
+    }
+
+    fn with_identifiers(
+        writer: W,
+        identifiers: Vec<Vec<u8>>,
+        thin: bool,
+    ) -> GnuBuilder<W> {
         let mut short_names = HashSet::<Vec<u8>>::new();
         let mut long_names = HashMap::<Vec<u8>, usize>::new();
         let mut name_table_size: usize = 0;
         for identifier in identifiers.into_iter() {
             let length = identifier.len();
-            if length > 15 {
-                long_names.insert(identifier, name_table_size);
-                name_table_size += length + 2;
+            // Thin archives store every member path in the name table.
+            if thin || length > 15 {
+                // A name given more than once is only stored once.
+                if let hash_map::Entry::Vacant(entry) =
+                    long_names.entry(identifier)
+                {
+                    entry.insert(name_table_size);
+                    name_table_size += length + 2;
+                }
             } else {
                 short_names.insert(identifier);
             }
         }
-        let name_table_needs_padding = name_table_size % 2 != 0;
+        let name_table_needs_padding = !name_table_size.is_multiple_of(2);
         if name_table_needs_padding {
             name_table_size += 3; // ` /\n`
         }
 
         GnuBuilder {
-            writer,
+            output: Output::new(writer),
+            options: Options::new(),
             short_names,
             long_names,
             name_table_size,
             name_table_needs_padding,
-            started: false,
+            symbol_table: None,
+            incremental: false,
+            thin,
         }
     }
 
-    /// Unwrap this archive builder, returning the underlying writer object.
-    pub fn into_inner(self) -> Result<W> {
-        Ok(self.writer)
+    /// Create a new archive builder that will write a GNU symbol table (the
+    /// `/` member) in front of all other entries.  Use `append_with_symbols()`
+    /// to record which symbols each entry defines.  If any member would start
+    /// beyond the first 4 GB of the archive, the 64-bit `/SYM64/` symbol
+    /// table is written instead.
+    ///
+    /// Because the symbol table must precede the entries it refers to, and
+    /// its size isn't known until every entry has been added, all entries are
+    /// buffered (in memory, or in a temporary file once they are larger than
+    /// the threshold set by `set_spill_threshold()`) and nothing is written
+    /// to the underlying writer until `finish()` (or `into_inner()`) is
+    /// called.
+    pub fn new_with_symbol_table(
+        writer: W,
+        identifiers: Vec<Vec<u8>>,
+    ) -> GnuBuilder<W> {
+        let mut builder = GnuBuilder::new(writer, identifiers);
+        builder.symbol_table = Some(SymbolTableBuffer::new());
+        builder
     }
 
-    /// Adds a new entry to this archive.
-    pub fn append<R: Read>(
-        &mut self,
-        header: &Header,
-        mut data: R,
-    ) -> Result<()> {
-        let is_long_name = header.identifier().len() > 15;
+    /// Create a new archive builder that doesn't need to be told the entry
+    /// identifiers up front; instead, the name table is built up from the
+    /// identifiers of the entries as they are appended.
+    ///
+    /// Because the name table must precede the entries that refer to it, and
+    /// its size isn't known until every entry has been added, all entries are
+    /// buffered (in memory, or in a temporary file once they are larger than
+    /// the threshold set by `set_spill_threshold()`) and nothing is written to
+    /// the underlying writer until `finish()` (or `into_inner()`) is called.
+    /// When the identifiers are known in advance, `GnuBuilder::new()` avoids
+    /// this buffering.
+    pub fn new_incremental(writer: W) -> GnuBuilder<W> {
+        let mut builder = GnuBuilder::new(writer, Vec::new());
+        builder.symbol_table = Some(SymbolTableBuffer::new());
+        builder.incremental = true;
+        builder
+    }
+
+    shared_builder_methods!();
+
+    /// Writes out the buffered entries, symbol table, and name table, if
+    /// there are any.
+    fn write_buffered(&mut self) -> io::Result<()> {
+        if self.incremental {
+            if let Some(mut table) = self.symbol_table.take() {
+                self.name_table_needs_padding =
+                    !self.name_table_size.is_multiple_of(2);
+                if self.name_table_needs_padding {
+                    self.name_table_size += 3; // ` /\n`
+                }
+                self.output.writer.write_all(self.global_header())?;
+                self.write_name_table()?;
+                table.members.copy_to(&mut self.output.writer)?;
+            }
+        } else if let Some(mut table) = self.symbol_table.take() {
+            self.output.writer.write_all(self.global_header())?;
+            let mut table_start =
+                (GLOBAL_HEADER_LEN + ENTRY_HEADER_LEN) as u64;
+            if !self.long_names.is_empty() {
+                table_start +=
+                    (ENTRY_HEADER_LEN + self.name_table_size) as u64;
+            }
+            let wide =
+                gnu_symbol_table_needs_64_bits(&table.symbols, table_start);
+            let members_start =
+                table_start + gnu_symbol_table_size(&table.symbols, wide);
+            write_gnu_symbol_table(
+                &mut self.output.writer,
+                &table.symbols,
+                members_start,
+                wide,
+            )?;
+            self.write_name_table()?;
+            table.members.copy_to(&mut self.output.writer)?;
+        } else if !self.output.started {
+            // Even an archive with no entries needs its global header.
+            self.output.writer.write_all(self.global_header())?;
+            self.write_name_table()?;
+            self.output.started = true;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes written to the underlying writer so far,
+    /// including the global header, the name table, and every entry's header,
+    /// data, and padding byte.  Since a builder that writes a symbol table, or
+    /// an incremental one, buffers all entries until `finish()`, this stays at
+    /// zero for one until then.
+    pub fn bytes_written(&self) -> u64 {
+        self.output.writer.count
+    }
+
+    /// Adds a new entry to this archive.  For a thin archive, only the header
+    /// is written and `data` is not read.
+    ///
+    /// Returns an error if the entry's identifier is empty or contains a
+    /// newline, or (except in a thin archive, whose identifiers are paths)
+    /// if it contains a slash, since GNU archives use a slash to terminate
+    /// names.  Even in a thin archive, the identifier can't end with a
+    /// slash, since readers strip exactly one trailing slash from each name.
+    pub fn append<R: Read>(&mut self, header: &Header, data: R) -> Result<()> {
+        self.output.check_not_finished()?;
+        check_identifier(header.identifier())?;
+        if header.identifier().ends_with(b"/") {
+            let msg = format!(
+                "Identifier {:?} ends with a slash",
+                String::from_utf8_lossy(header.identifier())
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        if !self.thin && header.identifier().contains(&b'/') {
+            let msg = format!(
+                "Identifier {:?} contains a slash",
+                String::from_utf8_lossy(header.identifier())
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        let is_long_name = self.thin || header.identifier().len() > 15;
         let has_name = if is_long_name {
             self.long_names.contains_key(header.identifier())
         } else {
             self.short_names.contains(header.identifier())
         };
         if !has_name {
-            let msg = format!(
-                "Identifier {:?} was not in the list of \
-                 identifiers passed to GnuBuilder::new()",
-                String::from_utf8_lossy(header.identifier())
-            );
-            return Err(Error::new(ErrorKind::InvalidInput, msg));
+            let identifier = header.identifier().to_vec();
+            if !self.incremental {
+                return Err(ArError::UndeclaredIdentifier(identifier));
+            }
+            if is_long_name {
+                let length = identifier.len();
+                self.long_names.insert(identifier, self.name_table_size);
+                self.name_table_size += length + 2;
+            } else {
+                self.short_names.insert(identifier);
+            }
         }
+        self.options.check_duplicate(header.identifier())?;
+        let adjusted_header;
+        let header = if self.options.deterministic {
+            adjusted_header = header.to_deterministic();
+            &adjusted_header
+        } else if self.options.clamp_ids {
+            adjusted_header = header.to_clamped_ids();
+            &adjusted_header
+        } else {
+            header
+        };
 
-        if !self.started {
-            self.writer.write_all(GLOBAL_HEADER)?;
-            if !self.long_names.is_empty() {
-                writeln!(
-                    self.writer,
-                    "{:<48}{:<10}`",
-                    GNU_NAME_TABLE_ID, self.name_table_size
-                )?;
-                let mut entries: Vec<(usize, &[u8])> = self
-                    .long_names
-                    .iter()
-                    .map(|(id, &start)| (start, id.as_slice()))
-                    .collect();
-                entries.sort();
-                for (_, id) in entries {
-                    self.writer.write_all(id)?;
-                    self.writer.write_all(b"/\n")?;
-                }
-                if self.name_table_needs_padding {
-                    self.writer.write_all(b" /\n")?;
-                }
+        let pad_byte = self.options.pad_byte;
+        let identifier = Some(header.identifier());
+        let threshold = self.options.spill_threshold;
+        if let Some(ref mut table) = self.symbol_table {
+            let long_names = &self.long_names;
+            let thin = self.thin;
+            // The member's offset isn't known until the table is written.
+            table
+                .append(threshold, |writer| {
+                    header.write_gnu(writer, long_names)?;
+                    if thin {
+                        return Ok(());
+                    }
+                    write_data(writer, header, data, pad_byte)
+                })
+                .map_err(|error| {
+                    member_context(error, "writing", identifier, None)
+                })?;
+            self.options.record_identifier(header.identifier());
+            self.output.members += 1;
+            return Ok(());
+        }
+
+        if !self.output.started {
+            self.output.writer.write_all(self.global_header())?;
+            self.write_name_table()?;
+            self.output.started = true;
+        }
+
+        let offset = Some(self.output.writer.count);
+        let result = match header
+            .write_gnu(&mut self.output.writer, &self.long_names)
+        {
+            Ok(()) if !self.thin => {
+                write_data(&mut self.output.writer, header, data, pad_byte)
             }
-            self.started = true;
+            result => result,
+        };
+        result.map_err(|error| {
+            member_context(error, "writing", identifier, offset)
+        })?;
+        self.options.record_identifier(header.identifier());
+        self.output.members += 1;
+        Ok(())
+    }
+
+    /// Adds a member to this thin archive that refers to the file at the
+    /// given path.  The path is recorded as-is as the member's identifier, so
+    /// it should be relative to the directory that the archive will be
+    /// stored in, and it must have been included in the list passed to
+    /// `GnuBuilder::new_thin()`.  The file's data is not read.
+    pub fn append_thin_member<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<()> {
+        if !self.thin {
+            let msg = "GnuBuilder was not created with GnuBuilder::new_thin()";
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
         }
+        let identifier = osstr_to_bytes(path.as_ref().as_os_str())?;
+        let metadata = path.as_ref().metadata()?;
+        let header = Header::from_metadata(identifier, &metadata);
+        self.append(&header, io::empty())
+    }
 
-        header.write_gnu(&mut self.writer, &self.long_names)?;
-        let actual_size = io::copy(&mut data, &mut self.writer)?;
-        if actual_size != header.size() {
-            let msg = format!(
-                "Wrong file size (header.size() = {}, actual \
-                               size was {})",
+    fn global_header(&self) -> &'static [u8] {
+        if self.thin {
+            THIN_MAGIC
+        } else {
+            COMMON_MAGIC
+        }
+    }
+
+    /// Adds each of the given entries to this archive in turn, as with
+    /// `append()`, stopping at the first error.
+    pub fn append_all<I, R>(&mut self, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Header, R)>,
+        R: Read,
+    {
+        for (header, data) in entries {
+            self.append(&header, data)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a new entry to this archive, whose data is read from `data`
+    /// until it runs out, rather than being `header.size()` bytes long.  The
+    /// header's size field is ignored, and replaced with the actual size.
+    ///
+    /// Since the size must be written before the data, the data is first
+    /// read into a buffer, which is kept in memory if it is no larger than
+    /// the threshold set by `set_spill_threshold()`, and otherwise written
+    /// to a temporary file.  Either way, the data is copied one more time
+    /// than by `append()`, which should be preferred when the size is known.
+    pub fn append_unknown_size<R: Read>(
+        &mut self,
+        header: &Header,
+        data: R,
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let (size, mut spooled) =
+            spool_data(data, self.options.spill_threshold)?;
+        let mut header = header.clone();
+        header.set_size(size);
+        self.append(&header, &mut spooled)
+    }
+
+    /// Adds a new entry to this archive, like `append()`, except that all of
+    /// the data is read (and its size checked) before anything is written.
+    /// If reading the data fails, or it turns out to be shorter or longer
+    /// than `header.size()`, an error is returned and the archive is left as
+    /// it was, so more entries can still be appended; with `append()`, the
+    /// archive would be left with a partially written entry.  (An error from
+    /// the underlying writer itself can still leave a partial entry.)
+    ///
+    /// The data is buffered as by `append_unknown_size()`, in memory or, if
+    /// larger than the threshold set by `set_spill_threshold()`, in a
+    /// temporary file.
+    pub fn append_atomic<R: Read>(
+        &mut self,
+        header: &Header,
+        data: R,
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let data = data.take(header.size().saturating_add(1));
+        let (size, mut spooled) =
+            spool_data(data, self.options.spill_threshold)?;
+        if size != header.size() {
+            return Err(wrong_size_error(
+                header.identifier(),
                 header.size(),
-                actual_size
-            );
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+                size,
+            )
+            .into());
+        }
+        self.append(header, &mut spooled)
+    }
+
+    /// Copies every remaining entry (header and data) of an existing archive
+    /// into this archive, so that several archives can be combined without
+    /// extracting them.  The identifier of each entry must have been included
+    /// in the list passed to `GnuBuilder::new()`, so that long filenames can
+    /// be stored in this archive's name table.  Special entries of the source
+    /// archive, such as its symbol table, are not copied.  Thin archives
+    /// can't be copied, since they don't contain their members' data.
+    pub fn append_archive<R: Read>(
+        &mut self,
+        archive: &mut Archive<R>,
+    ) -> Result<()> {
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry?;
+            check_not_thin(&entry)?;
+            let header = entry.header().clone();
+            self.append(&header, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a new entry to this archive, recording that it defines the given
+    /// symbols.  This builder must have been created with
+    /// `GnuBuilder::new_with_symbol_table()`.
+    pub fn append_with_symbols<R: Read>(
+        &mut self,
+        header: &Header,
+        data: R,
+        symbols: &[&[u8]],
+    ) -> Result<()> {
+        self.output.check_not_finished()?;
+        let offset = match self.symbol_table {
+            Some(ref table) if !self.incremental => table.members.len(),
+            _ => {
+                let msg = "GnuBuilder was not created with \
+                           GnuBuilder::new_with_symbol_table()";
+                return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+            }
+        };
+        self.append(header, data)?;
+        if let Some(ref mut table) = self.symbol_table {
+            table.add_symbols(symbols, offset);
+        }
+        Ok(())
+    }
+
+    /// Writes a `/` symbol table member with `capacity` bytes of zeroed data
+    /// (rounded up to an even number, but at least the four bytes of the
+    /// symbol count) as the first member of the archive, to be filled in
+    /// later.  Zeroed data is a valid table with no symbols, so the archive
+    /// can be read in the meantime.  This is the first step of building an
+    /// archive in two passes, as `ar rc` and then `ranlib` do: once all the
+    /// members have been written, and their offsets are known, a second pass
+    /// can seek back and overwrite the member (whose header starts just
+    /// after the global header, at offset 8) with the real symbol table,
+    /// without moving any other member.
+    ///
+    /// This builder's writer doesn't need to be seekable, but the second
+    /// pass does, so the archive has to end up somewhere like a file.  The
+    /// real table must be written with the same size as the one reserved
+    /// (padding its string table with NUL bytes), so `capacity` has to be
+    /// large enough for it, including the four-byte offset of each symbol.
+    /// This must be called before any entries are appended, and can't be
+    /// used with a builder that writes its own symbol table (or, as with
+    /// `GnuBuilder::new_incremental()`, buffers its members until the end).
+    pub fn reserve_symbol_table(&mut self, capacity: usize) -> Result<()> {
+        self.output.check_not_finished()?;
+        if self.output.started || self.symbol_table.is_some() {
+            let msg = "reserve_symbol_table() must be called before any \
+                       entries are appended, and without a symbol table \
+                       being built";
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
         }
-        if actual_size % 2 != 0 {
-            self.writer.write_all(&[b'\n'])?;
+        let size = cmp::max(capacity, 4) as u64;
+        let size = size + size % 2;
+        if size.to_string().len() > 10 {
+            let value = size.to_string();
+            let field = "file size";
+            return Err(ArError::FieldOverflow { field, value, width: 10 });
         }
+        self.output.writer.write_all(self.global_header())?;
+        writeln!(
+            self.output.writer,
+            "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
+            "/", 0, 0, 0, 0, size
+        )?;
+        io::copy(&mut io::repeat(0).take(size), &mut self.output.writer)?;
+        self.write_name_table()?;
+        self.output.started = true;
+        Ok(())
+    }
 
+    fn write_name_table(&mut self) -> io::Result<()> {
+        if self.long_names.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            self.output.writer,
+            "{:<48}{:<10}`",
+            GNU_NAME_TABLE_ID, self.name_table_size
+        )?;
+        let mut entries: Vec<(usize, &[u8])> = self
+            .long_names
+            .iter()
+            .map(|(id, &start)| (start, id.as_slice()))
+            .collect();
+        entries.sort();
+        for (_, id) in entries {
+            self.output.writer.write_all(id)?;
+            self.output.writer.write_all(b"/\n")?;
+        }
+        if self.name_table_needs_padding {
+            self.output.writer.write_all(b" /\n")?;
+        }
         Ok(())
     }
 
     /// Adds a file on the local filesystem to this archive, using the file
     /// name as its identifier.
     pub fn append_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let name: &OsStr = path.as_ref().file_name().ok_or_else(|| {
-            let msg = "Given path doesn't have a file name";
-            Error::new(ErrorKind::InvalidInput, msg)
-        })?;
-        let identifier = osstr_to_bytes(name)?;
-        let mut file = File::open(&path)?;
+        let identifier = file_name_identifier(path.as_ref())?;
+        let mut file = open_path(path.as_ref(), self.options.follow_symlinks)?;
         self.append_file_id(identifier, &mut file)
     }
 
+    /// Adds a file on the local filesystem to this archive, using the given
+    /// name as its identifier rather than the file name from the path.
+    pub fn append_path_with_name<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: &[u8],
+    ) -> Result<()> {
+        check_identifier(name)?;
+        let mut file = open_path(path.as_ref(), self.options.follow_symlinks)?;
+        self.append_file_id(name.to_vec(), &mut file)
+    }
+
     /// Adds a file to this archive, with the given name as its identifier.
     pub fn append_file(&mut self, name: &[u8], file: &mut File) -> Result<()> {
         self.append_file_id(name.to_vec(), file)
@@ -225,44 +1112,706 @@ impl<W: Write> GnuBuilder<W> {
     }
 }
 
-#[cfg(unix)]
-fn osstr_to_bytes(string: &OsStr) -> Result<Vec<u8>> {
-    Ok(string.as_bytes().to_vec())
+impl<W: Write> fmt::Debug for GnuBuilder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GnuBuilder")
+            .field("thin", &self.thin)
+            .field("started", &self.output.started)
+            .field("finished", &self.output.finished)
+            .field("members", &self.output.members)
+            .field("bytes_written", &self.output.writer.count)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Re-encodes every entry of `src` (regardless of how far `next_entry()` has
+/// advanced) as a new archive of the given variant, written to `out`, e.g.
+/// to turn a BSD archive into a GNU one.  Long filenames are encoded as the
+/// target variant requires; since the common variant has no way to store
+/// them, converting an archive with any identifier longer than 16 bytes (or
+/// containing a space) to it fails with an error.
+///
+/// If `src` has a symbol table, it is regenerated for `Variant::BSD` and
+/// `Variant::GNU` targets, with each symbol pointing at its member's new
+/// offset; it is dropped for `Variant::Common`, which has no symbol table.
+/// Symbols that don't point at the start of any member, as can be left in a
+/// stale table, are dropped rather than failing the conversion.  As with
+/// `Builder::append_archive()`, thin archives can't be converted, and
+/// neither can any archive be converted to `Variant::AIX`, which is
+/// read-only.
+pub fn convert<R: Read + Seek, W: Write>(
+    src: &mut Archive<R>,
+    variant: Variant,
+    out: W,
+) -> Result<()> {
+    let index = src.index()?;
+    let mut member_symbols = vec![Vec::<Vec<u8>>::new(); index.len()];
+    let has_symbols = {
+        let symbols = src.symbols()?;
+        let mut has_symbols = false;
+        for (symbol, offset) in symbols {
+            has_symbols = true;
+            let member = index
+                .iter()
+                .position(|&(_, header_start, _)| header_start == offset);
+            if let Some(member) = member {
+                member_symbols[member].push(symbol.to_vec());
+            }
+        }
+        has_symbols
+    };
+    match variant {
+        Variant::Common | Variant::BSD => {
+            let with_symbols = has_symbols && variant == Variant::BSD;
+            let mut builder = if with_symbols {
+                Builder::new_with_symbol_table(out)
+            } else {
+                Builder::new(out)
+            };
+            builder.set_auto_bsd(variant == Variant::BSD);
+            for (member, symbols) in member_symbols.iter().enumerate() {
+                let mut entry = src.jump_to_entry(member)?;
+                check_not_thin(&entry)?;
+                let header = entry.header().clone();
+                if with_symbols {
+                    let symbols: Vec<&[u8]> =
+                        symbols.iter().map(Vec::as_slice).collect();
+                    builder.append_with_symbols(&header, entry, &symbols)?;
+                } else {
+                    builder.append(&header, &mut entry)?;
+                }
+            }
+            builder.into_inner().map(|_| ())
+        }
+        Variant::GNU => {
+            let identifiers =
+                index.into_iter().map(|(identifier, _, _)| identifier);
+            let identifiers = identifiers.collect();
+            let mut builder = if has_symbols {
+                GnuBuilder::new_with_symbol_table(out, identifiers)
+            } else {
+                GnuBuilder::new(out, identifiers)
+            };
+            for (member, symbols) in member_symbols.iter().enumerate() {
+                let mut entry = src.jump_to_entry(member)?;
+                check_not_thin(&entry)?;
+                let header = entry.header().clone();
+                if has_symbols {
+                    let symbols: Vec<&[u8]> =
+                        symbols.iter().map(Vec::as_slice).collect();
+                    builder.append_with_symbols(&header, entry, &symbols)?;
+                } else {
+                    builder.append(&header, &mut entry)?;
+                }
+            }
+            builder.into_inner().map(|_| ())
+        }
+        Variant::AIX => {
+            let msg = "AIX big archives can't be written";
+            Err(Error::new(ErrorKind::InvalidInput, msg).into())
+        }
+    }
+}
+
+/// Opens a file to be added to an archive, or returns an error if it's a
+/// symbolic link and `follow_symlinks` is false.
+fn open_path(path: &Path, follow_symlinks: bool) -> io::Result<File> {
+    if !follow_symlinks && fs::symlink_metadata(path)?.file_type().is_symlink()
+    {
+        let msg = format!("Path {:?} is a symbolic link", path);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    File::open(path)
 }
 
-#[cfg(not(unix))]
-fn osstr_to_bytes(string: &OsStr) -> Result<Vec<u8>> {
-    let utf8: &str = string.to_str().ok_or_else(|| {
-        Error::new(ErrorKind::InvalidData, "Non-UTF8 file name")
-    })?;
-    Ok(utf8.as_bytes().to_vec())
+fn check_not_thin<R: Read>(entry: &Entry<'_, R>) -> io::Result<()> {
+    if entry.thin {
+        let msg = "Cannot copy entries from a thin archive";
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{Builder, GnuBuilder, Header};
-    use std::io::{Read, Result};
-    use std::str;
+fn check_identifier(identifier: &[u8]) -> io::Result<()> {
+    if identifier.is_empty() {
+        let msg = "Identifier must not be empty";
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    if identifier.contains(&b'\n') {
+        let msg = format!(
+            "Identifier {:?} contains a newline",
+            String::from_utf8_lossy(identifier)
+        );
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
 
-    struct SlowReader<'a> {
-        current_position: usize,
-        buffer: &'a [u8],
+// When copying into a `BufWriter`, `io::copy()` flushes the buffer whenever
+// less than 8 KiB of it is free, so the buffer must be a good deal larger than
+// that to combine the writes for small entries.
+const BUFFER_SIZE: usize = 64 << 10;
+
+/// The options that `Builder` and `GnuBuilder` share, as set by the methods
+/// of `shared_builder_methods!()`.
+struct Options {
+    deterministic: bool,
+    clamp_ids: bool,
+    spill_threshold: usize,
+    // The identifiers appended so far, if duplicates are being refused.
+    seen_identifiers: Option<HashSet<Vec<u8>>>,
+    follow_symlinks: bool,
+    pad_byte: u8,
+}
+
+impl Options {
+    fn new() -> Options {
+        Options {
+            deterministic: false,
+            clamp_ids: false,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            seen_identifiers: None,
+            follow_symlinks: true,
+            pad_byte: b'\n',
+        }
     }
 
-    impl<'a> Read for SlowReader<'a> {
-        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-            if self.current_position >= self.buffer.len() {
-                return Ok(0);
+    /// Returns an error if duplicate identifiers are being refused and an
+    /// entry with the given one has already been appended.
+    fn check_duplicate(&self, identifier: &[u8]) -> io::Result<()> {
+        if let Some(ref seen) = self.seen_identifiers {
+            if seen.contains(identifier) {
+                let identifier = identifier.to_vec();
+                return Err(ArError::DuplicateIdentifier(identifier).into());
             }
-            buf[0] = self.buffer[self.current_position];
-            self.current_position += 1;
-            return Ok(1);
         }
+        Ok(())
     }
 
-    #[test]
-    fn build_common_archive() {
-        let mut builder = Builder::new(Vec::new());
+    /// Records that an entry with the given identifier has been appended.
+    /// This is only done once the entry has been written, so that one which
+    /// failed to be written can be appended again.
+    fn record_identifier(&mut self, identifier: &[u8]) {
+        if let Some(ref mut seen) = self.seen_identifiers {
+            seen.insert(identifier.to_vec());
+        }
+    }
+}
+
+/// The state that `Builder` and `GnuBuilder` keep about the archive written so
+/// far, and about finishing it.
+struct Output<W> {
+    writer: CountingWriter<W>,
+    members: usize,
+    started: bool,
+    finished: bool,
+    // The error that writing the buffered data failed with, if it did.
+    finish_error: Option<(ErrorKind, String)>,
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: W) -> Output<W> {
+        Output {
+            writer: CountingWriter::new(writer),
+            members: 0,
+            started: false,
+            finished: false,
+            finish_error: None,
+        }
+    }
+
+    fn check_not_finished(&self) -> io::Result<()> {
+        if self.finished {
+            let msg = "Cannot append entries after calling finish()";
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the buffered data still has to be written out by
+    /// `finish()`, or the error that doing so failed with before.
+    fn check_finishing(&self) -> io::Result<bool> {
+        if let Some((kind, ref msg)) = self.finish_error {
+            return Err(Error::new(kind, msg.clone()));
+        }
+        Ok(!self.finished)
+    }
+
+    /// Records the result of writing out the buffered data, after which no
+    /// more entries can be appended.  If it failed, part of the data may
+    /// have been written, so the archive can't be completed by trying again.
+    fn end_writing(&mut self, result: io::Result<()>) -> io::Result<()> {
+        if let Err(ref error) = result {
+            self.finish_error = Some((error.kind(), error.to_string()));
+        }
+        self.finished = true;
+        result
+    }
+}
+
+/// A writer that counts the bytes written through it.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> CountingWriter<W> {
+        CountingWriter { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const DEFAULT_SPILL_THRESHOLD: usize = 1 << 20;
+
+/// Entry data of a previously unknown size, buffered in memory or in a
+/// temporary file by `spool_data()`.
+enum Spooled {
+    Memory(io::Cursor<Vec<u8>>),
+    File(TempFile),
+}
+
+impl Read for Spooled {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Spooled::Memory(ref mut cursor) => cursor.read(buf),
+            Spooled::File(ref mut temp) => {
+                temp.file.as_mut().unwrap().read(buf)
+            }
+        }
+    }
+}
+
+/// A temporary file, which is deleted when this is dropped.
+struct TempFile {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new() -> io::Result<TempFile> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = env::temp_dir().join(format!(
+                "ar-spool-{}-{}",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let mut options = OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            // Don't let other users read the data.
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(file) => return Ok(TempFile { file: Some(file), path }),
+                Err(ref error) if error.kind() == ErrorKind::AlreadyExists => {
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // Close the file first, since some platforms can't delete open files.
+        self.file.take();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads all of `data`, keeping it in memory if it is no more than
+/// `threshold` bytes long and spilling it to a temporary file otherwise.
+/// Returns the size of the data along with a reader for it.
+fn spool_data<R: Read>(
+    mut data: R,
+    threshold: usize,
+) -> io::Result<(u64, Spooled)> {
+    let mut buffer = Vec::new();
+    let limit = (threshold as u64).saturating_add(1);
+    data.by_ref().take(limit).read_to_end(&mut buffer)?;
+    if buffer.len() <= threshold {
+        let size = buffer.len() as u64;
+        return Ok((size, Spooled::Memory(io::Cursor::new(buffer))));
+    }
+    let mut temp = TempFile::new()?;
+    let size = {
+        let file = temp.file.as_mut().unwrap();
+        file.write_all(&buffer)?;
+        let size = buffer.len() as u64 + io::copy(&mut data, file)?;
+        file.seek(SeekFrom::Start(0))?;
+        size
+    };
+    Ok((size, Spooled::File(temp)))
+}
+
+// ========================================================================= //
+
+/// Entries that have been fully encoded, along with the symbols they define,
+/// waiting for a leading symbol table (or, for an incremental `GnuBuilder`,
+/// name table) to be written.
+struct SymbolTableBuffer {
+    members: SpooledMembers,
+    symbols: Vec<(Vec<u8>, u64)>,
+}
+
+impl SymbolTableBuffer {
+    fn new() -> SymbolTableBuffer {
+        SymbolTableBuffer {
+            members: SpooledMembers { memory: Vec::new(), file: None, len: 0 },
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Encodes an entry with `write_entry`, moving all of the encoded
+    /// entries to a temporary file once they are over `threshold` bytes.
+    fn append<F>(&mut self, threshold: usize, write_entry: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut SpooledMembers) -> io::Result<()>,
+    {
+        let start = self.members.len();
+        let result = write_entry(&mut self.members);
+        if result.is_err() {
+            // Don't leave a partially-written entry in the buffer.
+            self.members.truncate(start)?;
+            return result;
+        }
+        if self.members.len() > threshold as u64 {
+            self.members.spill()?;
+        }
+        Ok(())
+    }
+
+    fn add_symbols(&mut self, symbols: &[&[u8]], offset: u64) {
+        self.symbols
+            .extend(symbols.iter().map(|&symbol| (symbol.to_vec(), offset)));
+    }
+}
+
+/// Encoded entries, kept in memory until they outgrow the spill threshold
+/// and in a temporary file after that.
+struct SpooledMembers {
+    memory: Vec<u8>,
+    file: Option<TempFile>,
+    len: u64,
+}
+
+impl SpooledMembers {
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        match self.file {
+            Some(ref mut temp) => {
+                let file = temp.file.as_mut().unwrap();
+                file.set_len(len)?;
+                file.seek(SeekFrom::Start(len))?;
+            }
+            None => self.memory.truncate(len as usize),
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if self.file.is_none() {
+            let mut temp = TempFile::new()?;
+            temp.file.as_mut().unwrap().write_all(&self.memory)?;
+            self.memory = Vec::new();
+            self.file = Some(temp);
+        }
+        Ok(())
+    }
+
+    fn copy_to<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        match self.file {
+            Some(ref mut temp) => {
+                let file = temp.file.as_mut().unwrap();
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, writer)?;
+            }
+            None => writer.write_all(&self.memory)?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for SpooledMembers {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self.file {
+            Some(ref mut temp) => temp.file.as_mut().unwrap().write(buf)?,
+            None => self.memory.write(buf)?,
+        };
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn gnu_symbol_table_size(symbols: &[(Vec<u8>, u64)], wide: bool) -> u64 {
+    let width = if wide { 8 } else { 4 };
+    let strings_size: usize =
+        symbols.iter().map(|(symbol, _)| symbol.len() + 1).sum();
+    let size = (width + width * symbols.len() + strings_size) as u64;
+    size + size % 2
+}
+
+/// Returns true if a GNU symbol table needs 64-bit offsets (that is, must be
+/// a `/SYM64/` member rather than a `/` member), given the headers and name
+/// table that come before it (ending at `table_start`).
+fn gnu_symbol_table_needs_64_bits(
+    symbols: &[(Vec<u8>, u64)],
+    table_start: u64,
+) -> bool {
+    let members_start = table_start + gnu_symbol_table_size(symbols, false);
+    symbols
+        .iter()
+        .any(|&(_, offset)| members_start + offset > u64::from(u32::MAX))
+}
+
+/// Writes a GNU symbol table member, where each symbol's offset is relative
+/// to `members_start`.  If `wide` is true, this is a `/SYM64/` member, whose
+/// offsets are 64 bits long.
+fn write_gnu_symbol_table<W: Write>(
+    writer: &mut W,
+    symbols: &[(Vec<u8>, u64)],
+    members_start: u64,
+    wide: bool,
+) -> io::Result<()> {
+    let size = gnu_symbol_table_size(symbols, wide);
+    let identifier = if wide { "/SYM64/" } else { "/" };
+    writeln!(
+        writer,
+        "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
+        identifier, 0, 0, 0, 0, size
+    )?;
+    let write_be = |writer: &mut W, value: u64| {
+        if wide {
+            writer.write_all(&value.to_be_bytes())
+        } else if value > u64::from(u32::MAX) {
+            let msg = "Symbol table offset doesn't fit in 32 bits";
+            Err(Error::new(ErrorKind::InvalidData, msg))
+        } else {
+            writer.write_all(&(value as u32).to_be_bytes())
+        }
+    };
+    write_be(writer, symbols.len() as u64)?;
+    for &(_, offset) in symbols {
+        write_be(writer, members_start + offset)?;
+    }
+    let width = if wide { 8 } else { 4 };
+    let mut written = width * (1 + symbols.len() as u64);
+    for (symbol, _) in symbols {
+        writer.write_all(symbol)?;
+        writer.write_all(b"\0")?;
+        written += symbol.len() as u64 + 1;
+    }
+    if written < size {
+        writer.write_all(b"\0")?;
+    }
+    Ok(())
+}
+
+fn bsd_symbol_table_strings_size(symbols: &[(Vec<u8>, u64)]) -> u64 {
+    let size: usize = symbols.iter().map(|(symbol, _)| symbol.len() + 1).sum();
+    // Keep the string table (and hence the whole member) 4-byte aligned.
+    (size.div_ceil(4) * 4) as u64
+}
+
+fn bsd_symbol_table_size(
+    symbols: &[(Vec<u8>, u64)],
+    identifier: &[u8],
+) -> u64 {
+    let name_len = padded_bsd_symbol_table_id_len(identifier) as u64;
+    name_len
+        + 4
+        + 8 * symbols.len() as u64
+        + 4
+        + bsd_symbol_table_strings_size(symbols)
+}
+
+fn padded_bsd_symbol_table_id_len(identifier: &[u8]) -> usize {
+    identifier.len().div_ceil(4) * 4
+}
+
+/// Writes a BSD symbol table member with the given identifier (`__.SYMDEF`
+/// or `__.SYMDEF SORTED`), where each symbol's offset is relative to
+/// `members_start`.
+fn write_bsd_symbol_table<W: Write>(
+    writer: &mut W,
+    symbols: &[(Vec<u8>, u64)],
+    members_start: u64,
+    identifier: &[u8],
+) -> io::Result<()> {
+    let name_len = padded_bsd_symbol_table_id_len(identifier);
+    writeln!(
+        writer,
+        "#1/{:<13}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
+        name_len,
+        0,
+        0,
+        0,
+        0,
+        bsd_symbol_table_size(symbols, identifier)
+    )?;
+    writer.write_all(identifier)?;
+    writer.write_all(&vec![0; name_len - identifier.len()])?;
+    writer.write_all(&(8 * symbols.len() as u32).to_le_bytes())?;
+    let mut string_offset: u32 = 0;
+    for (symbol, offset) in symbols {
+        let offset = members_start + offset;
+        if offset > u64::from(u32::MAX) {
+            let msg = "Symbol table offset doesn't fit in 32 bits";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        writer.write_all(&string_offset.to_le_bytes())?;
+        writer.write_all(&(offset as u32).to_le_bytes())?;
+        string_offset += symbol.len() as u32 + 1;
+    }
+    let strings_size = bsd_symbol_table_strings_size(symbols);
+    writer.write_all(&(strings_size as u32).to_le_bytes())?;
+    for (symbol, _) in symbols {
+        writer.write_all(symbol)?;
+        writer.write_all(b"\0")?;
+    }
+    let padding = strings_size - u64::from(string_offset);
+    writer.write_all(&vec![0; padding as usize])?;
+    Ok(())
+}
+
+/// Copies exactly `header.size()` bytes of entry data, followed by the given
+/// padding byte if needed.  Returns an error as soon as `data` turns out to
+/// be longer than that, rather than reading it to the end.
+fn write_data<W: Write, R: Read>(
+    writer: &mut W,
+    header: &Header,
+    data: R,
+    pad_byte: u8,
+) -> io::Result<()> {
+    copy_data(writer, header.identifier(), header.size(), data, pad_byte)
+}
+
+/// Like `write_data()`, for an entry with the given identifier and size,
+/// which are only used to check the amount of data and report errors.
+fn copy_data<W: Write, R: Read>(
+    writer: &mut W,
+    identifier: &[u8],
+    size: u64,
+    mut data: R,
+    pad_byte: u8,
+) -> io::Result<()> {
+    let actual_size = io::copy(&mut data.by_ref().take(size), writer)?;
+    if actual_size < size {
+        return Err(wrong_size_error(identifier, size, actual_size));
+    }
+    let mut extra = [0u8; 1];
+    if read_some(&mut data, &mut extra)? > 0 {
+        return Err(wrong_size_error(identifier, size, actual_size + 1));
+    }
+    if actual_size % 2 != 0 {
+        writer.write_all(&[pad_byte])?;
+    }
+    Ok(())
+}
+
+/// Returns the identifier field of an encoded entry header, without its
+/// trailing spaces, for use in error messages.
+fn raw_identifier(header_bytes: &[u8; ENTRY_HEADER_LEN]) -> &[u8] {
+    let field = &header_bytes[..16];
+    let length =
+        field.iter().rposition(|&byte| byte != b' ').map_or(0, |i| i + 1);
+    &field[..length]
+}
+
+/// Returns the error for entry data that doesn't match the size in the
+/// entry's header.  Data longer than that is reported without its length,
+/// since it isn't read to the end.
+fn wrong_size_error(identifier: &[u8], size: u64, actual_size: u64) -> Error {
+    let msg = if actual_size < size {
+        format!(
+            "Wrong file size for {:?} (header.size() = {}, actual size was \
+             {})",
+            String::from_utf8_lossy(identifier),
+            size,
+            actual_size
+        )
+    } else {
+        format!(
+            "Wrong file size for {:?} (header.size() = {}, but the data is \
+             longer)",
+            String::from_utf8_lossy(identifier),
+            size
+        )
+    };
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Like `Read::read`, but retries if interrupted.
+fn read_some<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match reader.read(buf) {
+            Err(ref error) if error.kind() == ErrorKind::Interrupted => {}
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, Builder, GnuBuilder, Header};
+    use crate::{Archive, Error as ArError, Variant};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Cursor, Error, ErrorKind, Read, Result, Write};
+    use std::path::PathBuf;
+    use std::process;
+    use std::str;
+
+    struct SlowReader<'a> {
+        current_position: usize,
+        buffer: &'a [u8],
+    }
+
+    impl<'a> Read for SlowReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.current_position >= self.buffer.len() {
+                return Ok(0);
+            }
+            buf[0] = self.buffer[self.current_position];
+            self.current_position += 1;
+            Ok(1)
+        }
+    }
+
+    fn make_test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "ar-builder-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn build_common_archive() {
+        let mut builder = Builder::new(Vec::new());
         let mut header1 = Header::new(b"foo.txt".to_vec(), 7);
         header1.set_mtime(1487552916);
         header1.set_uid(501);
@@ -284,6 +1833,7 @@ mod tests {
     #[test]
     fn build_bsd_archive_with_long_filenames() {
         let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
         let mut header1 = Header::new(b"short".to_vec(), 1);
         header1.set_identifier(b"this_is_a_very_long_filename.txt".to_vec());
         header1.set_mtime(1487552916);
@@ -307,9 +1857,47 @@ mod tests {
         assert_eq!(str::from_utf8(&actual).unwrap(), expected);
     }
 
+    #[test]
+    fn build_bsd_archive_with_odd_length_data_and_long_filename() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        let header = Header::new(b"seventeen_chars.o".to_vec(), 3);
+        builder.append(&header, "abc".as_bytes()).unwrap();
+        let header = Header::new(b"short.o".to_vec(), 2);
+        builder.append(&header, "de".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        // The name is padded to 20 bytes, so the padding byte after the 3
+        // bytes of data keeps the next header on an even boundary.
+        let expected = "\
+        !<arch>\n\
+        #1/20           0           0     0     0       23        `\n\
+        seventeen_chars.o\x00\x00\x00abc\n\
+        short.o         0           0     0     0       2         `\n\
+        de";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let mut archive = Archive::new(actual.as_slice());
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"seventeen_chars.o");
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], b"abc");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"short.o");
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], b"de");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
     #[test]
     fn build_bsd_archive_with_space_in_filename() {
         let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
         let header = Header::new(b"foo bar".to_vec(), 4);
         builder.append(&header, "baz\n".as_bytes()).unwrap();
         let actual = builder.into_inner().unwrap();
@@ -321,76 +1909,1093 @@ mod tests {
     }
 
     #[test]
-    fn build_gnu_archive() {
-        let names = vec![b"baz.txt".to_vec(), b"foo.txt".to_vec()];
-        let mut builder = GnuBuilder::new(Vec::new(), names);
-        let mut header1 = Header::new(b"foo.txt".to_vec(), 7);
-        header1.set_mtime(1487552916);
-        header1.set_uid(501);
-        header1.set_gid(20);
-        header1.set_mode(0o100644);
-        builder.append(&header1, "foobar\n".as_bytes()).unwrap();
-        let header2 = Header::new(b"baz.txt".to_vec(), 4);
-        builder.append(&header2, "baz\n".as_bytes()).unwrap();
+    fn build_archive_from_slow_reader() {
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"foo.txt".to_vec(), 7);
+        let reader =
+            SlowReader { current_position: 0, buffer: "foobar\n".as_bytes() };
+        builder.append(&header, reader).unwrap();
         let actual = builder.into_inner().unwrap();
         let expected = "\
         !<arch>\n\
-        foo.txt/        1487552916  501   20    100644  7         `\n\
-        foobar\n\n\
-        baz.txt/        0           0     0     0       4         `\n\
-        baz\n";
+        foo.txt         0           0     0     0       7         `\n\
+        foobar\n\n";
         assert_eq!(str::from_utf8(&actual).unwrap(), expected);
     }
 
     #[test]
-    fn build_gnu_archive_with_long_filenames() {
-        let names = vec![
-            b"this_is_a_very_long_filename.txt".to_vec(),
-            b"and_this_is_another_very_long_filename.txt".to_vec(),
-        ];
+    fn create_and_open_archive_files() {
+        let dir = make_test_dir("create_open");
+        let path = dir.join("foo.a");
+        let mut builder = Builder::create(&path).unwrap();
+        let header = Header::new(b"foo.txt".to_vec(), 7);
+        builder.append(&header, "foobar\n".as_bytes()).unwrap();
+        builder.into_inner().unwrap();
+
+        let gnu_path = dir.join("bar.a");
+        let ids = vec![b"this_is_a_very_long_filename.txt".to_vec()];
+        let mut builder = GnuBuilder::create(&gnu_path, ids.clone()).unwrap();
+        let header = Header::new(ids[0].clone(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        builder.into_inner().unwrap();
+
+        let mut archive = Archive::open(&path).unwrap();
+        assert_eq!(archive.names().unwrap(), [b"foo.txt".to_vec()]);
+        let mut archive = Archive::open(&gnu_path).unwrap();
+        assert_eq!(archive.names().unwrap(), ids);
+        assert_eq!(archive.variant(), Variant::GNU);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let error = Archive::open(dir.join("missing.a")).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn build_archive_from_files() {
+        let dir = make_test_dir("from_files");
+        File::create(dir.join("foo.txt"))
+            .unwrap()
+            .write_all(b"foobar\n")
+            .unwrap();
+        File::create(dir.join("baz.txt"))
+            .unwrap()
+            .write_all(b"baz\n")
+            .unwrap();
+        let mut builder = Builder::new(Vec::new());
+        builder.append_path(dir.join("foo.txt")).unwrap();
+        let mut file = File::open(dir.join("baz.txt")).unwrap();
+        builder.append_file(b"hello.txt", &mut file).unwrap();
+        let actual = builder.into_inner().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = Archive::new(actual.as_slice());
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.txt");
+            assert_eq!(entry.header().size(), 7);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], b"foobar\n");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"hello.txt");
+            assert_eq!(entry.header().size(), 4);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], b"baz\n");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_archive_from_symlinked_path() {
+        use std::os::unix::fs::symlink;
+        let dir = make_test_dir("symlinked_path");
+        fs::write(dir.join("foo.txt"), b"foobar\n").unwrap();
+        symlink(dir.join("foo.txt"), dir.join("link.txt")).unwrap();
+
+        let mut builder = Builder::new(Vec::new());
+        builder.append_path(dir.join("link.txt")).unwrap();
+        builder.set_follow_symlinks(false);
+        let error = builder.append_path(dir.join("link.txt")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = builder
+            .append_path_with_name(dir.join("link.txt"), b"bar.txt")
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        builder.append_path(dir.join("foo.txt")).unwrap();
+        let actual = builder.into_inner().unwrap();
+
+        let names = vec![b"link.txt".to_vec()];
         let mut builder = GnuBuilder::new(Vec::new(), names);
-        let mut header1 = Header::new(b"short".to_vec(), 1);
-        header1.set_identifier(b"this_is_a_very_long_filename.txt".to_vec());
-        header1.set_mtime(1487552916);
-        header1.set_uid(501);
-        header1.set_gid(20);
-        header1.set_mode(0o100644);
-        header1.set_size(7);
-        builder.append(&header1, "foobar\n".as_bytes()).unwrap();
-        let header2 = Header::new(
-            b"and_this_is_another_very_long_filename.txt".to_vec(),
-            4,
+        builder.set_follow_symlinks(false);
+        let error = builder.append_path(dir.join("link.txt")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = Archive::new(actual.as_slice());
+        let mut entries = Vec::new();
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry.unwrap();
+            let data = entry.read_all().unwrap();
+            entries.push((entry.header().identifier().to_vec(), data));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (b"link.txt".to_vec(), b"foobar\n".to_vec()),
+                (b"foo.txt".to_vec(), b"foobar\n".to_vec()),
+            ]
         );
-        builder.append(&header2, "baz\n".as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn build_archive_from_directory_tree() {
+        let dir = make_test_dir("dir_all");
+        fs::create_dir_all(dir.join("sub/deeper")).unwrap();
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        fs::write(dir.join("foo.txt"), b"foobar\n").unwrap();
+        fs::write(dir.join("sub/bar.txt"), b"bar\n").unwrap();
+        fs::write(dir.join("sub/deeper/baz.txt"), b"baz\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+            symlink(dir.join("foo.txt"), dir.join("link.txt")).unwrap();
+            symlink(&dir, dir.join("sub/loop")).unwrap();
+        }
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        builder.append_dir_all(&dir).unwrap();
         let actual = builder.into_inner().unwrap();
-        let expected = "\
-        !<arch>\n\
-        //                                              78        `\n\
-        this_is_a_very_long_filename.txt/\n\
-        and_this_is_another_very_long_filename.txt/\n\
-        /0              1487552916  501   20    100644  7         `\n\
-        foobar\n\n\
-        /34             0           0     0     0       4         `\n\
-        baz\n";
-        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = Archive::new(actual.as_slice());
+        let mut entries = Vec::new();
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry.unwrap();
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            entries.push((entry.header().identifier().to_vec(), buffer));
+        }
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (b"foo.txt".to_vec(), b"foobar\n".to_vec()),
+                (b"sub/bar.txt".to_vec(), b"bar\n".to_vec()),
+                (b"sub/deeper/baz.txt".to_vec(), b"baz\n".to_vec()),
+            ]
+        );
     }
 
     #[test]
-    fn build_gnu_archive_with_space_in_filename() {
-        let names = vec![b"foo bar".to_vec()];
-        let mut builder = GnuBuilder::new(Vec::new(), names);
-        let header = Header::new(b"foo bar".to_vec(), 4);
+    fn build_archive_by_merging_archives() {
+        let names = vec![b"this_is_a_very_long_filename.txt".to_vec()];
+        let mut builder =
+            GnuBuilder::new_with_symbol_table(Vec::new(), names.clone());
+        let header = Header::new(names[0].clone(), 7);
+        builder
+            .append_with_symbols(&header, "foobar\n".as_bytes(), &[b"foo"])
+            .unwrap();
+        let gnu_input = builder.into_inner().unwrap();
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"baz.txt".to_vec(), 4);
         builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let bsd_input = builder.into_inner().unwrap();
+
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        builder.append_archive(&mut Archive::new(&gnu_input[..])).unwrap();
+        builder.append_archive(&mut Archive::new(&bsd_input[..])).unwrap();
         let actual = builder.into_inner().unwrap();
         let expected = "\
         !<arch>\n\
-        foo bar/        0           0     0     0       4         `\n\
+        #1/32           0           0     0     0       39        `\n\
+        this_is_a_very_long_filename.txtfoobar\n\n\
+        baz.txt         0           0     0     0       4         `\n\
         baz\n";
         assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let mut names = vec![b"baz.txt".to_vec()];
+        names.extend(Archive::new(&gnu_input[..]).names().unwrap());
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        builder.append_archive(&mut Archive::new(&actual[..])).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(Cursor::new(actual));
+        assert_eq!(
+            archive.names().unwrap(),
+            vec![
+                b"this_is_a_very_long_filename.txt".to_vec(),
+                b"baz.txt".to_vec()
+            ]
+        );
+        assert_eq!(archive.symbols().unwrap().len(), 0);
+        assert_eq!(archive.variant(), Variant::GNU);
     }
 
     #[test]
-    #[should_panic(
+    fn build_archive_from_path_with_name() {
+        let dir = make_test_dir("path_with_name");
+        let path = dir.join("foo.txt");
+        File::create(&path).unwrap().write_all(b"foobar\n").unwrap();
+        let mut builder = Builder::new(Vec::new());
+        builder.append_path_with_name(&path, b"renamed.txt").unwrap();
+        let error = builder.append_path_with_name(&path, b"").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error =
+            builder.append_path_with_name(&path, b"foo\nbar").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let actual = builder.into_inner().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = Archive::new(actual.as_slice());
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"renamed.txt");
+            assert_eq!(entry.header().size(), 7);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer).unwrap();
+            assert_eq!(&buffer as &[u8], b"foobar\n");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn build_bsd_archive_with_symbol_table() {
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        builder.set_auto_bsd(true);
+        let header1 = Header::new(b"foo.o".to_vec(), 4);
+        builder
+            .append_with_symbols(
+                &header1,
+                "foo\n".as_bytes(),
+                &[b"foo", b"bar"],
+            )
+            .unwrap();
+        let header2 =
+            Header::new(b"this_is_a_very_long_filename.o".to_vec(), 3);
+        builder
+            .append_with_symbols(&header2, "baz".as_bytes(), &[b"quux"])
+            .unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = b"\
+        !<arch>\n\
+        #1/12           0           0     0     0       60        `\n\
+        __.SYMDEF\x00\x00\x00\x18\x00\x00\x00\
+        \x00\x00\x00\x00\x80\x00\x00\x00\
+        \x04\x00\x00\x00\x80\x00\x00\x00\
+        \x08\x00\x00\x00\xc0\x00\x00\x00\
+        \x10\x00\x00\x00foo\x00bar\x00quux\x00\x00\x00\x00\
+        foo.o           0           0     0     0       4         `\n\
+        foo\n\
+        #1/32           0           0     0     0       35        `\n\
+        this_is_a_very_long_filename.o\x00\x00baz\n";
+        assert_eq!(actual, expected as &[u8]);
+
+        let mut archive = Archive::new(Cursor::new(actual));
+        let symbols: Vec<(Vec<u8>, u64)> = archive
+            .symbols()
+            .unwrap()
+            .map(|(symbol, offset)| (symbol.to_vec(), offset))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (b"foo".to_vec(), 128),
+                (b"bar".to_vec(), 128),
+                (b"quux".to_vec(), 192)
+            ]
+        );
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.o");
+        }
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(
+                entry.header().identifier(),
+                b"this_is_a_very_long_filename.o"
+            );
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn build_bsd_archive_with_sorted_symbol_table() {
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        builder.set_auto_bsd(true);
+        builder.set_sort_symbols(true);
+        let header1 = Header::new(b"foo.o".to_vec(), 4);
+        builder
+            .append_with_symbols(
+                &header1,
+                "foo\n".as_bytes(),
+                &[b"foo", b"bar"],
+            )
+            .unwrap();
+        let header2 =
+            Header::new(b"this_is_a_very_long_filename.o".to_vec(), 3);
+        builder
+            .append_with_symbols(&header2, "baz".as_bytes(), &[b"quux"])
+            .unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = b"\
+        !<arch>\n\
+        #1/16           0           0     0     0       64        `\n\
+        __.SYMDEF SORTED\x18\x00\x00\x00\
+        \x00\x00\x00\x00\x84\x00\x00\x00\
+        \x04\x00\x00\x00\x84\x00\x00\x00\
+        \x08\x00\x00\x00\xc4\x00\x00\x00\
+        \x10\x00\x00\x00bar\x00foo\x00quux\x00\x00\x00\x00\
+        foo.o           0           0     0     0       4         `\n\
+        foo\n\
+        #1/32           0           0     0     0       35        `\n\
+        this_is_a_very_long_filename.o\x00\x00baz\n";
+        assert_eq!(actual, expected as &[u8]);
+
+        let mut archive = Archive::new_seekable(Cursor::new(actual));
+        let symbols: Vec<(Vec<u8>, u64)> = archive
+            .symbols()
+            .unwrap()
+            .map(|(symbol, offset)| (symbol.to_vec(), offset))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (b"bar".to_vec(), 132),
+                (b"foo".to_vec(), 132),
+                (b"quux".to_vec(), 196)
+            ]
+        );
+        archive.symbols().unwrap().validate().unwrap();
+        let entry = archive.entry_for_symbol(b"quux").unwrap().unwrap();
+        assert_eq!(
+            entry.header().identifier(),
+            b"this_is_a_very_long_filename.o"
+        );
+    }
+
+    #[test]
+    fn find_entries_defining_symbols() {
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        builder.set_auto_bsd(true);
+        let header1 = Header::new(b"foo.o".to_vec(), 4);
+        builder
+            .append_with_symbols(
+                &header1,
+                "foo\n".as_bytes(),
+                &[b"foo", b"bar"],
+            )
+            .unwrap();
+        let header2 =
+            Header::new(b"this_is_a_very_long_filename.o".to_vec(), 3);
+        builder
+            .append_with_symbols(&header2, "baz".as_bytes(), &[b"quux"])
+            .unwrap();
+        let actual = builder.into_inner().unwrap();
+
+        let mut archive = Archive::new(Cursor::new(actual));
+        {
+            let symbols = archive.symbols().unwrap();
+            assert_eq!(symbols.lookup(b"foo"), Some(128));
+            assert_eq!(symbols.lookup(b"bar"), Some(128));
+            assert_eq!(symbols.lookup(b"quux"), Some(192));
+            assert_eq!(symbols.lookup(b"missing"), None);
+        }
+        for &(symbol, identifier) in &[
+            (&b"bar"[..], &b"foo.o"[..]),
+            (b"quux", b"this_is_a_very_long_filename.o"),
+            (b"foo", b"foo.o"),
+        ] {
+            let entry = archive.entry_for_symbol(symbol).unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), identifier);
+        }
+        {
+            let mut entry =
+                archive.entry_for_symbol(b"quux").unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"baz");
+        }
+        assert!(archive.entry_for_symbol(b"missing").unwrap().is_none());
+
+        let symbols = vec![(b"foo".to_vec(), 0)];
+        let mut input = b"!<arch>\n".to_vec();
+        super::write_gnu_symbol_table(&mut input, &symbols, 1000, false)
+            .unwrap();
+        let mut archive = Archive::new(Cursor::new(input));
+        let error = archive.entry_for_symbol(b"foo").err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn build_deterministic_archive() {
+        let build = |mtime: u64| {
+            let mut builder = Builder::new(Vec::new());
+            builder.set_deterministic(true);
+            let mut header = Header::new(b"foo.txt".to_vec(), 7);
+            header.set_mtime(mtime);
+            header.set_uid(501);
+            header.set_gid(20);
+            header.set_mode(0o100755);
+            builder.append(&header, "foobar\n".as_bytes()).unwrap();
+            builder.into_inner().unwrap()
+        };
+        let actual = build(1487552916);
+        assert_eq!(actual, build(1487552919));
+        let expected = "\
+        !<arch>\n\
+        foo.txt         0           0     0     644     7         `\n\
+        foobar\n\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+    }
+
+    #[test]
+    fn build_gnu_archive() {
+        let names = vec![b"baz.txt".to_vec(), b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let mut header1 = Header::new(b"foo.txt".to_vec(), 7);
+        header1.set_mtime(1487552916);
+        header1.set_uid(501);
+        header1.set_gid(20);
+        header1.set_mode(0o100644);
+        builder.append(&header1, "foobar\n".as_bytes()).unwrap();
+        let header2 = Header::new(b"baz.txt".to_vec(), 4);
+        builder.append(&header2, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        foo.txt/        1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        baz.txt/        0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+    }
+
+    #[test]
+    fn build_gnu_archive_with_long_filenames() {
+        let names = vec![
+            b"this_is_a_very_long_filename.txt".to_vec(),
+            b"and_this_is_another_very_long_filename.txt".to_vec(),
+        ];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let mut header1 = Header::new(b"short".to_vec(), 1);
+        header1.set_identifier(b"this_is_a_very_long_filename.txt".to_vec());
+        header1.set_mtime(1487552916);
+        header1.set_uid(501);
+        header1.set_gid(20);
+        header1.set_mode(0o100644);
+        header1.set_size(7);
+        builder.append(&header1, "foobar\n".as_bytes()).unwrap();
+        let header2 = Header::new(
+            b"and_this_is_another_very_long_filename.txt".to_vec(),
+            4,
+        );
+        builder.append(&header2, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        //                                              78        `\n\
+        this_is_a_very_long_filename.txt/\n\
+        and_this_is_another_very_long_filename.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n\n\
+        /34             0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+    }
+
+    #[test]
+    fn build_gnu_archive_with_symbol_table() {
+        let names = vec![
+            b"foo.o".to_vec(),
+            b"this_is_a_very_long_filename.o".to_vec(),
+        ];
+        let mut builder = GnuBuilder::new_with_symbol_table(Vec::new(), names);
+        let header1 = Header::new(b"foo.o".to_vec(), 4);
+        builder
+            .append_with_symbols(
+                &header1,
+                "foo\n".as_bytes(),
+                &[b"foo", b"bar"],
+            )
+            .unwrap();
+        let header2 =
+            Header::new(b"this_is_a_very_long_filename.o".to_vec(), 3);
+        builder
+            .append_with_symbols(&header2, "baz".as_bytes(), &[b"baz"])
+            .unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = b"\
+        !<arch>\n\
+        /               0           0     0     0       28        `\n\
+        \x00\x00\x00\x03\x00\x00\x00\xbc\x00\x00\x00\xbc\x00\x00\x00\xfc\
+        foo\x00bar\x00baz\x00\
+        //                                              32        `\n\
+        this_is_a_very_long_filename.o/\n\
+        foo.o/          0           0     0     0       4         `\n\
+        foo\n\
+        /0              0           0     0     0       3         `\n\
+        baz\n";
+        assert_eq!(actual, expected as &[u8]);
+
+        let mut archive = Archive::new(Cursor::new(actual));
+        let symbols: Vec<(Vec<u8>, u64)> = archive
+            .symbols()
+            .unwrap()
+            .map(|(symbol, offset)| (symbol.to_vec(), offset))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (b"foo".to_vec(), 188),
+                (b"bar".to_vec(), 188),
+                (b"baz".to_vec(), 252)
+            ]
+        );
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.o");
+        }
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(
+                entry.header().identifier(),
+                b"this_is_a_very_long_filename.o"
+            );
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn append_with_symbols_requires_symbol_table() {
+        let names = vec![b"foo.o".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let header = Header::new(b"foo.o".to_vec(), 4);
+        let error = builder
+            .append_with_symbols(&header, "foo\n".as_bytes(), &[b"foo"])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn build_gnu_archive_with_space_in_filename() {
+        let names = vec![b"foo bar".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let header = Header::new(b"foo bar".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        foo bar/        0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+    }
+
+    #[test]
+    fn build_gnu_archive_lazily_writes_global_header() {
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let header = Header::new(b"bar.txt".to_vec(), 4);
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        // A rejected entry shouldn't cause anything to be written.
+        assert_eq!(builder.bytes_written(), 0);
+    }
+
+    #[test]
+    fn build_empty_archives() {
+        let actual = Builder::new(Vec::new()).into_inner().unwrap();
+        assert_eq!(actual.as_slice(), b"!<arch>\n");
+        let mut archive = Archive::new(actual.as_slice());
+        assert!(archive.next_entry().is_none());
+
+        let builder = GnuBuilder::new(Vec::new(), Vec::new());
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(actual.as_slice(), b"!<arch>\n");
+
+        let builder = GnuBuilder::new_incremental(Vec::new());
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(actual.as_slice(), b"!<arch>\n");
+
+        let builder = GnuBuilder::new_thin(Vec::new(), Vec::new());
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(actual.as_slice(), b"!<thin>\n");
+        let mut archive = Archive::new(actual.as_slice());
+        assert!(archive.next_entry().is_none());
+
+        let dir = make_test_dir("empty");
+        let path = dir.join("empty.a");
+        Builder::create(&path).unwrap().finish().unwrap();
+        let mut archive = Archive::new(File::open(&path).unwrap());
+        assert!(archive.next_entry().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_archive_with_too_large_file_size() {
+        let header = Header::new(b"foo.txt".to_vec(), 10_000_000_000);
+        let mut builder = Builder::new(Vec::new());
+        let error = builder.append(&header, io::empty()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let error = builder.append(&header, io::empty()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        // The largest size that fits in the field is still accepted.
+        let header = Header::new(b"foo.txt".to_vec(), 9_999_999_999);
+        let mut output = Vec::new();
+        header.write(&mut output).unwrap();
+        assert_eq!(
+            str::from_utf8(&output).unwrap(),
+            "foo.txt         0           0     0     0       9999999999`\n"
+        );
+        // For a BSD long name, the name is counted as part of the size.
+        let header = Header::new(
+            b"this_is_a_very_long_filename.txt".to_vec(),
+            9_999_999_999,
+        );
+        let error = header.write(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn build_archive_with_wrong_data_size() {
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        let mut builder = Builder::new(Vec::new());
+        // An endless stream is rejected as soon as it exceeds the size.
+        let error = builder.append(&header, io::repeat(b'x')).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "while writing member \"foo.txt\" at offset 8: Wrong file size \
+             for \"foo.txt\" (header.size() = 4, but the data is longer)"
+        );
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let error = builder.append(&header, "baz".as_bytes()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "while writing member \"foo.txt\" at offset 8: Wrong file size \
+             for \"foo.txt\" (header.size() = 4, actual size was 3)"
+        );
+    }
+
+    #[test]
+    fn build_archive_with_too_large_uid() {
+        let mut header = Header::new(b"foo.txt".to_vec(), 4);
+        header.set_uid(10_000_000);
+        let mut builder = Builder::new(Vec::new());
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn build_header_with_chained_setters() {
+        let header = Header::builder(b"foo.txt".to_vec(), 4)
+            .mtime(1487552916)
+            .uid(501)
+            .gid(20)
+            .mode(0o100644)
+            .build()
+            .unwrap();
+        let mut builder = Builder::new(Vec::new());
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let error = Header::builder(b"foo.txt".to_vec(), 4)
+            .uid(10_000_000)
+            .build()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_invalid_identifiers() {
+        for identifier in [&b""[..], b"foo\n.txt", b"foo`\n"] {
+            let header = Header::new(identifier.to_vec(), 4);
+            let mut builder = Builder::new(Vec::new());
+            let error =
+                builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            assert_eq!(builder.bytes_written(), 0);
+        }
+
+        let identifiers =
+            vec![b"foo/bar.txt".to_vec(), b"this/is/a/long/name.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), identifiers.clone());
+        for identifier in identifiers.iter() {
+            let header = Header::new(identifier.clone(), 4);
+            let error =
+                builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        let mut builder = GnuBuilder::new_thin(Vec::new(), identifiers);
+        let header = Header::new(b"foo/bar.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn build_gnu_archive_incrementally() {
+        let entries: [(&[u8], &str); 3] = [
+            (b"this_is_a_very_long_filename.txt", "foobar\n"),
+            (b"baz.txt", "baz\n"),
+            (b"and_this_is_another_very_long_filename.txt", "quux\n"),
+        ];
+        let identifiers = entries.iter().map(|&(id, _)| id.to_vec()).collect();
+        let mut expected = GnuBuilder::new(Vec::new(), identifiers);
+        let mut builder = GnuBuilder::new_incremental(Vec::new());
+        for &(identifier, data) in entries.iter() {
+            let header = Header::new(identifier.to_vec(), data.len() as u64);
+            expected.append(&header, data.as_bytes()).unwrap();
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        let expected = expected.into_inner().unwrap();
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(
+            str::from_utf8(&actual).unwrap(),
+            str::from_utf8(&expected).unwrap()
+        );
+
+        let mut builder = GnuBuilder::new_incremental(Vec::new());
+        for &(identifier, data) in entries.iter().chain(&entries[..1]) {
+            let header = Header::new(identifier.to_vec(), data.len() as u64);
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(actual.as_slice());
+        let mut names = archive.names().unwrap();
+        assert_eq!(names.pop().unwrap(), entries[0].0);
+        assert_eq!(
+            names,
+            entries.iter().map(|&(id, _)| id).collect::<Vec<_>>()
+        );
+
+        let mut builder = GnuBuilder::new_incremental(Vec::new());
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        let error = builder
+            .append_with_symbols(&header, "foo\n".as_bytes(), &[b"foo"])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn buffered_members_spill_to_temp_file() {
+        let entries: [(&[u8], &str); 3] = [
+            (b"this_is_a_very_long_filename.txt", "foobar\n"),
+            (b"baz.txt", "baz\n"),
+            (b"and_this_is_another_very_long_filename.txt", "quux\n"),
+        ];
+        let identifiers = entries.iter().map(|&(id, _)| id.to_vec()).collect();
+        let mut expected = GnuBuilder::new(Vec::new(), identifiers);
+        let mut builder = GnuBuilder::new_incremental(Vec::new());
+        builder.set_spill_threshold(8);
+        for &(identifier, data) in entries.iter() {
+            let header = Header::new(identifier.to_vec(), data.len() as u64);
+            expected.append(&header, data.as_bytes()).unwrap();
+            builder.append(&header, data.as_bytes()).unwrap();
+            // An entry that fails part way through is left out.
+            let header = Header::new(b"short.txt".to_vec(), 10);
+            builder.append(&header, "abc".as_bytes()).unwrap_err();
+        }
+        assert!(builder.symbol_table.as_ref().unwrap().members.file.is_some());
+        let expected = expected.into_inner().unwrap();
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(
+            str::from_utf8(&actual).unwrap(),
+            str::from_utf8(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn append_data_of_unknown_size() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_spill_threshold(8);
+        let header = Header::new(b"foo.txt".to_vec(), 0);
+        builder.append_unknown_size(&header, "foobar\n".as_bytes()).unwrap();
+        let data = "This file is awesome!\n".repeat(10);
+        let header = Header::new(b"bar.txt".to_vec(), 1);
+        builder.append_unknown_size(&header, data.as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+
+        let mut archive = Archive::new(actual.as_slice());
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().size(), 7);
+            assert_eq!(entry.read_all().unwrap(), b"foobar\n");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().size(), 220);
+            assert_eq!(entry.read_all().unwrap(), data.as_bytes());
+        }
+        assert!(archive.next_entry().is_none());
+
+        // A threshold of usize::MAX means never spilling.
+        let mut builder = Builder::new(Vec::new());
+        builder.set_spill_threshold(usize::MAX);
+        let header = Header::new(b"bar.txt".to_vec(), 1);
+        builder.append_unknown_size(&header, data.as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(actual.as_slice());
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.read_all().unwrap(), data.as_bytes());
+    }
+
+    #[test]
+    fn temp_file_is_private_and_deleted() {
+        let temp = super::TempFile::new().unwrap();
+        let path = temp.path.clone();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn write_and_read_64_bit_gnu_symbol_table() {
+        let symbols = vec![(b"foo".to_vec(), 0), (b"bar".to_vec(), 5 << 30)];
+        assert!(!super::gnu_symbol_table_needs_64_bits(&symbols[..1], 8));
+        assert!(super::gnu_symbol_table_needs_64_bits(&symbols, 8));
+
+        // Pretend that the members start past the 4 GB boundary, without
+        // actually writing them.
+        let mut input = b"!<arch>\n".to_vec();
+        let members_start = 6 << 30;
+        super::write_gnu_symbol_table(
+            &mut input,
+            &symbols,
+            members_start,
+            true,
+        )
+        .unwrap();
+        assert_eq!(&input[8..24], b"/SYM64/         ");
+        input.extend_from_slice(
+            b"foo.txt/        0           0     0     644     4         `\n\
+              foo\n",
+        );
+        let mut archive = Archive::new(Cursor::new(input));
+        let actual: Vec<(&[u8], u64)> = archive.symbols().unwrap().collect();
+        let expected: Vec<(&[u8], u64)> =
+            vec![(b"foo", 6 << 30), (b"bar", 11 << 30)];
+        assert_eq!(actual, expected);
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"foo.txt");
+
+        let error = super::write_gnu_symbol_table(
+            &mut Vec::new(),
+            &symbols,
+            members_start,
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    struct FailingFlushWriter(Vec<u8>);
+
+    impl Write for FailingFlushWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Err(Error::other("flush failed"))
+        }
+    }
+
+    #[test]
+    fn finish_surfaces_flush_errors() {
+        let mut builder = Builder::new(FailingFlushWriter(Vec::new()));
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let error = builder.finish().unwrap_err();
+        assert_eq!(error.to_string(), "flush failed");
+        assert!(builder.into_inner().is_err());
+    }
+
+    /// Fails the first flush, and only that one.
+    struct FailFirstFlushWriter {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for FailFirstFlushWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.flushes += 1;
+            if self.flushes == 1 {
+                return Err(Error::other("flush failed"));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retrying_failed_flush_writes_archive_once() {
+        let writer = FailFirstFlushWriter { data: Vec::new(), flushes: 0 };
+        let mut builder = Builder::new_with_symbol_table(writer);
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder
+            .append_with_symbols(&header, "baz\n".as_bytes(), &[b"foo"])
+            .unwrap();
+        assert_eq!(builder.finish().unwrap_err().to_string(), "flush failed");
+        assert!(builder.append(&header, "baz\n".as_bytes()).is_err());
+        builder.finish().unwrap();
+        let total = builder.bytes_written();
+        let actual = builder.into_inner().unwrap().data;
+        assert_eq!(actual.len() as u64, total);
+        let mut archive = Archive::new(Cursor::new(actual));
+        assert_eq!(archive.count_entries().unwrap(), 1);
+
+        let writer = FailFirstFlushWriter { data: Vec::new(), flushes: 0 };
+        let mut builder = GnuBuilder::new_incremental(writer);
+        assert_eq!(builder.finish().unwrap_err().to_string(), "flush failed");
+        builder.finish().unwrap();
+        let actual = builder.into_inner().unwrap().data;
+        assert_eq!(actual.as_slice(), b"!<arch>\n");
+    }
+
+    /// Fails the first write that would take it past `limit` bytes.
+    struct FailOnceWriter {
+        data: Vec<u8>,
+        limit: usize,
+        failed: bool,
+    }
+
+    impl Write for FailOnceWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if !self.failed && self.data.len() + buf.len() > self.limit {
+                self.failed = true;
+                return Err(Error::other("write failed"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retrying_failed_finish_is_an_error() {
+        let writer =
+            FailOnceWriter { data: Vec::new(), limit: 70, failed: false };
+        let mut builder = Builder::new_with_symbol_table(writer);
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder
+            .append_with_symbols(&header, "baz\n".as_bytes(), &[b"foo"])
+            .unwrap();
+        assert_eq!(builder.finish().unwrap_err().to_string(), "write failed");
+        assert_eq!(builder.finish().unwrap_err().to_string(), "write failed");
+        assert!(builder.append(&header, "baz\n".as_bytes()).is_err());
+        assert!(builder.into_inner().is_err());
+
+        let writer =
+            FailOnceWriter { data: Vec::new(), limit: 70, failed: false };
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new_with_symbol_table(writer, names);
+        builder
+            .append_with_symbols(&header, "baz\n".as_bytes(), &[b"foo"])
+            .unwrap();
+        assert_eq!(builder.finish().unwrap_err().to_string(), "write failed");
+        assert_eq!(builder.finish().unwrap_err().to_string(), "write failed");
+        assert!(builder.into_inner().is_err());
+    }
+
+    #[test]
+    fn append_after_finish_is_rejected() {
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new_with_symbol_table(Vec::new(), names);
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder
+            .append_with_symbols(&header, "baz\n".as_bytes(), &[b"foo"])
+            .unwrap();
+        builder.finish().unwrap();
+        builder.finish().unwrap();
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(Cursor::new(actual));
+        let symbols: Vec<&[u8]> =
+            archive.symbols().unwrap().map(|(symbol, _)| symbol).collect();
+        assert_eq!(symbols, vec![b"foo" as &[u8]]);
+        assert_eq!(archive.count_entries().unwrap(), 1);
+    }
+
+    #[test]
+    fn gnu_identifier_ending_with_slash() {
+        let names = vec![b"foo/".to_vec(), b"foo".to_vec()];
+        let header = Header::new(b"foo/".to_vec(), 4);
+        let mut builder = GnuBuilder::new(Vec::new(), names.clone());
+        let error = builder.append(&header, "foo\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "Identifier \"foo/\" ends with a slash");
+
+        let mut builder = GnuBuilder::new_thin(Vec::new(), names);
+        let error = builder.append(&header, io::empty()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let header = Header::new(b"foo".to_vec(), 4);
+        builder.append(&header, io::empty()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(actual.as_slice());
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"foo");
+    }
+
+    #[test]
+    fn build_gnu_thin_archive() {
+        let names = vec![b"foo.o".to_vec(), b"dir/bar.o".to_vec()];
+        let mut builder = GnuBuilder::new_thin(Vec::new(), names);
+        let mut header1 = Header::new(b"foo.o".to_vec(), 1234);
+        header1.set_mode(0o100644);
+        builder.append(&header1, "ignored".as_bytes()).unwrap();
+        let mut header2 = Header::new(b"dir/bar.o".to_vec(), 7);
+        header2.set_mode(0o100644);
+        builder.append(&header2, "ignored".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<thin>\n\
+        //                                              18        `\n\
+        foo.o/\n\
+        dir/bar.o/\n\
+        /0              0           0     0     100644  1234      `\n\
+        /7              0           0     0     100644  7         `\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let mut archive = Archive::new(Cursor::new(actual));
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"foo.o");
+            assert_eq!(entry.header().size(), 1234);
+        }
+        {
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"dir/bar.o");
+        }
+        assert!(archive.next_entry().is_none());
+        assert!(archive.is_thin());
+    }
+
+    #[test]
+    fn build_gnu_thin_archive_from_paths() {
+        let dir = make_test_dir("thin");
+        let path = dir.join("foo.o");
+        fs::write(&path, b"foobar\n").unwrap();
+        let identifier = path.to_str().unwrap().as_bytes().to_vec();
+        let mut builder = GnuBuilder::new_thin(Vec::new(), vec![identifier]);
+        builder.append_thin_member(&path).unwrap();
+        let actual = builder.into_inner().unwrap();
+
+        let mut archive = Archive::new(Cursor::new(actual));
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().size(), 7);
+        let mut buffer = Vec::new();
+        let mut file = entry.open_thin_member("/").unwrap();
+        file.read_to_end(&mut buffer).unwrap();
+        assert_eq!(&buffer as &[u8], b"foobar\n");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut builder = GnuBuilder::new(Vec::new(), vec![]);
+        let error = builder.append_thin_member(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[should_panic(
         expected = "Identifier \\\"bar\\\" was not in the list of \
                                identifiers passed to GnuBuilder::new()"
     )]
@@ -400,4 +3005,748 @@ mod tests {
         let header = Header::new(b"bar".to_vec(), 4);
         builder.append(&header, "baz\n".as_bytes()).unwrap();
     }
+
+    #[test]
+    fn build_archive_refusing_duplicate_identifiers() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_dedup(true);
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        match error.without_context() {
+            ArError::DuplicateIdentifier(identifier) => {
+                assert_eq!(identifier, b"foo.txt");
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        let header = Header::new(b"bar.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(Cursor::new(actual));
+        assert_eq!(archive.count_entries().unwrap(), 2);
+
+        let names = vec![b"foo".to_vec(), b"this_is_a_long_name.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        builder.set_dedup(true);
+        let header = Header::new(b"this_is_a_long_name.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        // An undeclared identifier isn't recorded as seen.
+        let header = Header::new(b"bar".to_vec(), 4);
+        assert!(builder.append(&header, "baz\n".as_bytes()).is_err());
+        let header = Header::new(b"foo".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+
+        // Nor is one whose entry failed to be written, so it can be retried.
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        builder.set_dedup(true);
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        assert!(builder.append(&header, "ba".as_bytes()).is_err());
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        assert!(builder.append(&header, "baz\n".as_bytes()).is_err());
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(Cursor::new(actual));
+        assert_eq!(archive.count_entries().unwrap(), 1);
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        builder.set_dedup(true);
+        assert!(builder.append(&header, "ba".as_bytes()).is_err());
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+
+        // Duplicates are allowed by default.
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn build_buffered_archive() {
+        struct CountingWriter {
+            data: Vec<u8>,
+            writes: usize,
+        }
+
+        impl Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                self.writes += 1;
+                self.data.write(buf)
+            }
+
+            fn flush(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        fn append_entries<W: Write>(builder: &mut Builder<W>) {
+            for index in 0..100 {
+                let header = Header::new(format!("{}.o", index).into(), 4);
+                builder.append(&header, "baz\n".as_bytes()).unwrap();
+            }
+        }
+
+        let writer = CountingWriter { data: Vec::new(), writes: 0 };
+        let mut builder = Builder::new(writer);
+        append_entries(&mut builder);
+        let unbuffered = builder.into_inner().unwrap();
+
+        let writer = CountingWriter { data: Vec::new(), writes: 0 };
+        let mut builder = Builder::buffered(writer);
+        append_entries(&mut builder);
+        let buffered = builder.into_unbuffered().unwrap();
+
+        assert_eq!(buffered.data, unbuffered.data);
+        assert!(unbuffered.writes >= 200);
+        assert!(buffered.writes <= 2);
+    }
+
+    #[test]
+    fn build_common_archive_without_auto_bsd() {
+        // By default, such identifiers are rejected.
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"sixteen_chars.ab".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let header = Header::new(b"this_is_a_long_name.txt".to_vec(), 4);
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let header = Header::new(b"with space".to_vec(), 4);
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        sixteen_chars.ab0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        // With auto-BSD enabled, they use the BSD encoding instead.
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        let header = Header::new(b"sixteen_chars.ab".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let header = Header::new(b"with space".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        sixteen_chars.ab0           0     0     0       4         `\n\
+        baz\n\
+        #1/12           0           0     0     0       16        `\n\
+        with space\0\0baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+        let mut archive = Archive::new(actual.as_slice());
+        archive.next_entry().unwrap().unwrap();
+        let entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"with space");
+    }
+
+    #[test]
+    fn build_archive_with_append_all() {
+        let entries = vec![
+            (Header::new(b"foo.txt".to_vec(), 7), "foobar\n".as_bytes()),
+            (Header::new(b"baz.txt".to_vec(), 4), "baz\n".as_bytes()),
+        ];
+        let mut builder = Builder::new(Vec::new());
+        builder.append_all(entries.clone()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        foo.txt         0           0     0     0       7         `\n\
+        foobar\n\n\
+        baz.txt         0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let error = builder.append_all(entries).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(actual.as_slice());
+        assert_eq!(archive.len().unwrap(), 1);
+    }
+
+    #[test]
+    fn count_bytes_written() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        assert_eq!(builder.bytes_written(), 0);
+        let header = Header::new(b"foo.txt".to_vec(), 7);
+        builder.append(&header, "foobar\n".as_bytes()).unwrap();
+        assert_eq!(builder.bytes_written(), 8 + 60 + 8);
+        let header = Header::new(b"this_is_a_long_name.txt".to_vec(), 3);
+        builder.append(&header, "baz".as_bytes()).unwrap();
+        assert_eq!(builder.bytes_written(), 8 + 60 + 8 + 60 + 24 + 3 + 1);
+        let total = builder.bytes_written();
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(actual.len() as u64, total);
+
+        let names = vec![b"this_is_a_long_name.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let header = Header::new(b"this_is_a_long_name.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let total = builder.bytes_written();
+        let actual = builder.into_inner().unwrap();
+        assert_eq!(actual.len() as u64, total);
+
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        let header = Header::new(b"foo.o".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        assert_eq!(builder.bytes_written(), 0);
+        builder.finish().unwrap();
+        let total = builder.bytes_written();
+        assert_eq!(builder.into_inner().unwrap().len() as u64, total);
+    }
+
+    #[test]
+    fn build_archive_with_empty_entries() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        let header = Header::new(b"empty".to_vec(), 0);
+        builder.append(&header, io::empty()).unwrap();
+        let header = Header::new(b"this_is_a_long_empty_file".to_vec(), 0);
+        builder.append(&header, io::empty()).unwrap();
+        let header = Header::new(b"baz.txt".to_vec(), 4);
+        builder.append(&header, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        empty           0           0     0     0       0         `\n\
+        #1/28           0           0     0     0       28        `\n\
+        this_is_a_long_empty_file\0\0\0\
+        baz.txt         0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let names = vec![b"this_is_a_long_empty_file".to_vec(), b"x".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let header = Header::new(b"this_is_a_long_empty_file".to_vec(), 0);
+        builder.append(&header, io::empty()).unwrap();
+        let header = Header::new(b"x".to_vec(), 0);
+        builder.append(&header, io::empty()).unwrap();
+        let gnu = builder.into_inner().unwrap();
+
+        for (input, count) in [(actual, 3), (gnu, 2)] {
+            let mut archive = Archive::new(input.as_slice());
+            let mut entries = 0;
+            while let Some(entry) = archive.next_entry() {
+                let mut entry = entry.unwrap();
+                let size = entry.header().size();
+                assert_eq!(entry.bytes_remaining(), size);
+                let mut buffer = [0; 8];
+                if size == 0 {
+                    assert_eq!(entry.read(&mut buffer).unwrap(), 0);
+                }
+                assert_eq!(entry.read_all().unwrap().len() as u64, size);
+                entries += 1;
+            }
+            assert_eq!(entries, count);
+        }
+    }
+
+    #[test]
+    fn build_archive_with_pad_byte() {
+        for &pad_byte in &[b'\n', 0] {
+            let mut builder = Builder::new(Vec::new());
+            builder.set_pad_byte(pad_byte);
+            let header = Header::new(b"foo.txt".to_vec(), 3);
+            builder.append(&header, "foo".as_bytes()).unwrap();
+            let header = Header::new(b"bar.txt".to_vec(), 5);
+            builder.append(&header, "bar\n\n".as_bytes()).unwrap();
+            let bsd = builder.into_inner().unwrap();
+            assert_eq!(bsd[8 + 60 + 3], pad_byte);
+            assert_eq!(bsd.len(), 8 + 60 + 4 + 60 + 6);
+            assert_eq!(bsd[bsd.len() - 1], pad_byte);
+
+            let names = vec![b"foo.txt".to_vec(), b"bar.txt".to_vec()];
+            let mut builder = GnuBuilder::new(Vec::new(), names);
+            builder.set_pad_byte(pad_byte);
+            let header = Header::new(b"foo.txt".to_vec(), 3);
+            builder.append(&header, "foo".as_bytes()).unwrap();
+            let header = Header::new(b"bar.txt".to_vec(), 5);
+            builder.append(&header, "bar\n\n".as_bytes()).unwrap();
+            let gnu = builder.into_inner().unwrap();
+            assert_eq!(gnu[8 + 60 + 3], pad_byte);
+
+            for input in [bsd, gnu] {
+                let mut archive = Archive::new(input.as_slice());
+                archive.validate().unwrap();
+                let mut archive = Archive::new(input.as_slice());
+                let mut contents = Vec::new();
+                while let Some(entry) = archive.next_entry() {
+                    let mut entry = entry.unwrap();
+                    let identifier = entry.header().identifier().to_vec();
+                    let data = entry.read_all().unwrap();
+                    contents.push((identifier, data));
+                }
+                assert_eq!(
+                    contents,
+                    vec![
+                        (b"foo.txt".to_vec(), b"foo".to_vec()),
+                        (b"bar.txt".to_vec(), b"bar\n\n".to_vec()),
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn debug_builder_state() {
+        let mut builder = Builder::new(Vec::new());
+        assert_eq!(
+            format!("{:?}", builder),
+            "Builder { started: false, finished: false, members: 0, \
+             bytes_written: 0, .. }"
+        );
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let bad_header = Header::new(b"foo\n".to_vec(), 3);
+        assert!(builder.append(&bad_header, "foo".as_bytes()).is_err());
+        assert_eq!(
+            format!("{:?}", builder),
+            "Builder { started: true, finished: false, members: 1, \
+             bytes_written: 72, .. }"
+        );
+
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new_with_symbol_table(Vec::new(), names);
+        builder.append_with_symbols(&header, "foo\n".as_bytes(), &[]).unwrap();
+        assert_eq!(
+            format!("{:?}", builder),
+            "GnuBuilder { thin: false, started: false, finished: false, \
+             members: 1, bytes_written: 0, .. }"
+        );
+    }
+
+    #[test]
+    fn build_archive_with_raw_headers() {
+        let raw_header = |id: &str, size: u64| {
+            let mut header_bytes = [0u8; 60];
+            let field = format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                id, 1487552916, 501, 20, 100644, size
+            );
+            header_bytes.copy_from_slice(field.as_bytes());
+            header_bytes
+        };
+        let mut builder = Builder::new(Vec::new());
+        builder.set_pad_byte(0);
+        let header = raw_header("foo.txt", 3);
+        builder.append_raw(&header, "foo".as_bytes(), 3).unwrap();
+        // A BSD extended identifier is part of the data.
+        let header = raw_header("#1/20", 24);
+        let data = "this_is_a_long_name\0baz\n";
+        builder.append_raw(&header, data.as_bytes(), 24).unwrap();
+        let header = raw_header("bad.txt", 4);
+        let error = builder.append_raw(&header, "bad".as_bytes(), 4);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidData);
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        foo.txt         1487552916  501   20    100644  3         `\n\
+        foo\0\
+        #1/20           1487552916  501   20    100644  24        `\n\
+        this_is_a_long_name\0baz\n\
+        bad.txt         1487552916  501   20    100644  4         `\n\
+        bad";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let mut archive = Archive::new(&actual[..actual.len() - 63]);
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.read_all().unwrap(), b"foo");
+        }
+        {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), b"this_is_a_long_name");
+            assert_eq!(entry.read_all().unwrap(), b"baz\n");
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn ids_too_large_for_header_fields() {
+        let mut header = Header::new(b"foo.txt".to_vec(), 4);
+        header.set_uid(1000000);
+        let mut builder = Builder::new(Vec::new());
+        let error = builder.append(&header, "foo\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        match error.without_context() {
+            ArError::FieldOverflow { field: "owner ID", value, width: 6 } => {
+                assert_eq!(value, "1000000");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(builder.into_inner().unwrap(), b"!<arch>\n");
+
+        header.set_uid(1000);
+        header.set_gid(4294967295);
+        let names = vec![b"foo.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names.clone());
+        let error = builder.append(&header, "foo\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        header.set_uid(1000000);
+        let mut builder = Builder::new(Vec::new());
+        builder.set_clamp_ids(true);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let bsd = builder.into_inner().unwrap();
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        builder.set_clamp_ids(true);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let gnu = builder.into_inner().unwrap();
+        for input in [bsd, gnu] {
+            let mut archive = Archive::new(input.as_slice());
+            let entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().uid(), 999999);
+            assert_eq!(entry.header().gid(), 999999);
+        }
+    }
+
+    #[test]
+    fn reserve_gnu_symbol_table_for_later() {
+        let names = vec![b"this_is_a_long_name.o".to_vec(), b"bar.o".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names.clone());
+        builder.reserve_symbol_table(23).unwrap();
+        let header = Header::new(b"this_is_a_long_name.o".to_vec(), 4);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let header = Header::new(b"bar.o".to_vec(), 4);
+        builder.append(&header, "bar\n".as_bytes()).unwrap();
+        let mut output = builder.into_inner().unwrap();
+
+        let offsets = {
+            let mut archive = Archive::new_seekable(Cursor::new(&output));
+            assert_eq!(archive.symbols().unwrap().table_len(), 0);
+            let index = archive.index().unwrap();
+            index.iter().map(|&(_, offset, _)| offset).collect::<Vec<_>>()
+        };
+        // The name table is padded from 23 to 26 bytes.
+        assert_eq!(offsets, vec![8 + 60 + 24 + 60 + 26, 178 + 64]);
+
+        // Fill in the reserved table, as ranlib would.
+        let mut table = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "/", 0, 0, 0, 0, 24
+        )
+        .into_bytes();
+        table.extend_from_slice(&2u32.to_be_bytes());
+        table.extend_from_slice(&(offsets[0] as u32).to_be_bytes());
+        table.extend_from_slice(&(offsets[1] as u32).to_be_bytes());
+        table.extend_from_slice(b"foo\0bar\0");
+        table.resize(60 + 24, 0);
+        output[8..8 + 60 + 24].copy_from_slice(&table);
+        let mut archive = Archive::new_seekable(Cursor::new(&output));
+        let mut entry = archive.entry_for_symbol(b"bar").unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"bar.o");
+        assert_eq!(entry.read_all().unwrap(), b"bar\n");
+
+        let mut builder = GnuBuilder::new(Vec::new(), names.clone());
+        let header = Header::new(b"bar.o".to_vec(), 4);
+        builder.append(&header, "bar\n".as_bytes()).unwrap();
+        let error = builder.reserve_symbol_table(16).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut builder = GnuBuilder::new_with_symbol_table(Vec::new(), names);
+        let error = builder.reserve_symbol_table(16).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn append_errors_name_the_member() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> Result<usize> {
+                Err(Error::other("disk on fire"))
+            }
+        }
+
+        let mut builder = Builder::new(Vec::new());
+        let header = Header::new(b"foo.txt".to_vec(), 4);
+        builder.append(&header, "foo\n".as_bytes()).unwrap();
+        let header = Header::new(b"bar.txt".to_vec(), 4);
+        let error = builder.append(&header, FailingReader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Other);
+        assert_eq!(
+            error.to_string(),
+            "while writing member \"bar.txt\" at offset 72: disk on fire"
+        );
+        match error.without_context() {
+            ArError::Io(error) => {
+                assert_eq!(error.to_string(), "disk on fire")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Buffered members don't have an offset yet.
+        let names = vec![b"bar.txt".to_vec()];
+        let mut builder = GnuBuilder::new_with_symbol_table(Vec::new(), names);
+        let error = builder.append(&header, FailingReader).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "while writing member \"bar.txt\": disk on fire"
+        );
+
+        let mut header = Header::new(b"baz.txt".to_vec(), 4);
+        header.set_gid(1000000);
+        let mut builder = Builder::new_with_symbol_table(Vec::new());
+        let error = builder.append(&header, "baz\n".as_bytes()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("while writing member \"baz.txt\": Value 1000000"));
+        match error.without_context() {
+            ArError::FieldOverflow { field: "group ID", .. } => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn failed_append_atomic_leaves_archive_intact() {
+        struct FailingReader {
+            remaining: usize,
+        }
+
+        impl Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+                if self.remaining == 0 {
+                    return Err(Error::other("connection reset"));
+                }
+                let len = buf.len().min(self.remaining).min(2);
+                buf[..len].copy_from_slice(&b"ba"[..len]);
+                self.remaining -= len;
+                Ok(len)
+            }
+        }
+
+        let header = Header::new(b"foo.txt".to_vec(), 7);
+        let baz = Header::new(b"baz.txt".to_vec(), 4);
+
+        // A plain append leaves a partial entry behind.
+        let mut builder = Builder::new(Vec::new());
+        let data = FailingReader { remaining: 2 };
+        assert!(builder.append(&header, data).is_err());
+        assert_eq!(builder.bytes_written(), 8 + 60 + 2);
+
+        let mut builder = Builder::new(Vec::new());
+        let data = FailingReader { remaining: 2 };
+        assert!(builder.append_atomic(&header, data).is_err());
+        assert_eq!(builder.bytes_written(), 0);
+        let error =
+            builder.append_atomic(&header, "foo\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = builder
+            .append_atomic(&header, "foobar\nbaz".as_bytes())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        builder.append_atomic(&baz, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let expected = "\
+        !<arch>\n\
+        baz.txt         0           0     0     0       4         `\n\
+        baz\n";
+        assert_eq!(str::from_utf8(&actual).unwrap(), expected);
+
+        let names = vec![b"foo.txt".to_vec(), b"baz.txt".to_vec()];
+        let mut builder = GnuBuilder::new(Vec::new(), names);
+        let data = FailingReader { remaining: 4 };
+        assert!(builder.append_atomic(&header, data).is_err());
+        builder.append_atomic(&baz, "baz\n".as_bytes()).unwrap();
+        let actual = builder.into_inner().unwrap();
+        let mut archive = Archive::new(actual.as_slice());
+        let mut entry = archive.next_entry().unwrap().unwrap();
+        assert_eq!(entry.header().identifier(), b"baz.txt");
+        assert_eq!(entry.read_all().unwrap(), b"baz\n");
+    }
+
+    fn convert_source(variant: Variant, identifiers: &[&str]) -> Vec<u8> {
+        match variant {
+            Variant::Common => {
+                let mut builder = Builder::new(Vec::new());
+                for identifier in identifiers {
+                    let header = Header::new(
+                        identifier.as_bytes().to_vec(),
+                        identifier.len() as u64,
+                    );
+                    builder.append(&header, identifier.as_bytes()).unwrap();
+                }
+                builder.into_inner().unwrap()
+            }
+            Variant::BSD => {
+                let mut builder = Builder::new_with_symbol_table(Vec::new());
+                builder.set_auto_bsd(true);
+                for (index, identifier) in identifiers.iter().enumerate() {
+                    let header = Header::new(
+                        identifier.as_bytes().to_vec(),
+                        identifier.len() as u64,
+                    );
+                    let symbol = format!("sym_{}", index);
+                    builder
+                        .append_with_symbols(
+                            &header,
+                            identifier.as_bytes(),
+                            &[symbol.as_bytes()],
+                        )
+                        .unwrap();
+                }
+                builder.into_inner().unwrap()
+            }
+            Variant::GNU => {
+                let names = identifiers
+                    .iter()
+                    .map(|identifier| identifier.as_bytes().to_vec())
+                    .collect();
+                let mut builder =
+                    GnuBuilder::new_with_symbol_table(Vec::new(), names);
+                for (index, identifier) in identifiers.iter().enumerate() {
+                    let header = Header::new(
+                        identifier.as_bytes().to_vec(),
+                        identifier.len() as u64,
+                    );
+                    let symbol = format!("sym_{}", index);
+                    builder
+                        .append_with_symbols(
+                            &header,
+                            identifier.as_bytes(),
+                            &[symbol.as_bytes()],
+                        )
+                        .unwrap();
+                }
+                builder.into_inner().unwrap()
+            }
+            Variant::AIX => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn convert_with_repeated_identifiers() {
+        let mut builder = Builder::new(Vec::new());
+        builder.set_auto_bsd(true);
+        let entries: [(&[u8], &str); 3] = [
+            (b"a_very_long_member_name.o", "one\n"),
+            (b"a_very_long_member_name.o", "two\n"),
+            (b"x.o", "three\n"),
+        ];
+        for &(identifier, data) in entries.iter() {
+            let header = Header::new(identifier.to_vec(), data.len() as u64);
+            builder.append(&header, data.as_bytes()).unwrap();
+        }
+        let input = builder.into_inner().unwrap();
+        let mut archive = Archive::new_seekable(Cursor::new(&input));
+        let mut output = Vec::new();
+        convert(&mut archive, Variant::GNU, &mut output).unwrap();
+
+        let mut archive = Archive::new(output.as_slice());
+        for &(identifier, data) in entries.iter() {
+            let mut entry = archive.next_entry().unwrap().unwrap();
+            assert_eq!(entry.header().identifier(), identifier);
+            assert_eq!(entry.read_all().unwrap(), data.as_bytes());
+        }
+        assert!(archive.next_entry().is_none());
+    }
+
+    #[test]
+    fn convert_drops_dangling_symbols() {
+        // The symbol table has a second symbol, "bogus", pointing at an
+        // offset where there is no member.
+        let input = b"\
+        !<arch>\n\
+        /               0           0     0     0       25        `\n\
+        \x00\x00\x00\x02\x00\x00\x00\xbc\x00\x00\x09\x99\
+        foobar\x00bogus\x00\n\
+        //                                              34        `\n\
+        this_is_a_very_long_filename.txt/\n\
+        /0              1487552916  501   20    100644  7         `\n\
+        foobar\n";
+        for &target in [Variant::BSD, Variant::GNU].iter() {
+            let mut archive = Archive::new_seekable(Cursor::new(input));
+            let mut output = Vec::new();
+            convert(&mut archive, target, &mut output).unwrap();
+            let mut archive = Archive::new_seekable(Cursor::new(&output));
+            let index = archive.index().unwrap();
+            assert_eq!(index.len(), 1);
+            let symbols: Vec<(Vec<u8>, u64)> = archive
+                .symbols()
+                .unwrap()
+                .map(|(symbol, offset)| (symbol.to_vec(), offset))
+                .collect();
+            assert_eq!(symbols, vec![(b"foobar".to_vec(), index[0].1)]);
+        }
+    }
+
+    #[test]
+    fn convert_between_variants() {
+        let variants = [Variant::Common, Variant::BSD, Variant::GNU];
+        for &source in variants.iter() {
+            let identifiers: &[&str] = if source == Variant::Common {
+                &["foo.o", "bar.o"]
+            } else {
+                &["foo.o", "a_rather_long_name.o", "bar.o"]
+            };
+            let input = convert_source(source, identifiers);
+            for &target in variants.iter() {
+                let mut archive = Archive::new_seekable(Cursor::new(&input));
+                let mut output = Vec::new();
+                let result = convert(&mut archive, target, &mut output);
+                if target == Variant::Common && source != Variant::Common {
+                    let error = result.unwrap_err();
+                    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+                    continue;
+                }
+                result.unwrap();
+
+                // Without long names or a symbol table, an archive written by
+                // `Builder` can't be told apart from a common one.
+                let expected_variant =
+                    if source == Variant::Common && target == Variant::BSD {
+                        Variant::Common
+                    } else {
+                        target
+                    };
+                let mut cursor = Cursor::new(&output);
+                let detected = Variant::detect(&mut cursor).unwrap();
+                assert_eq!(detected, expected_variant, "{:?}", source);
+                let mut archive = Archive::new_seekable(cursor);
+                let index = archive.index().unwrap();
+                let names: Vec<&[u8]> = index
+                    .iter()
+                    .map(|(identifier, _, _)| identifier.as_slice())
+                    .collect();
+                let expected: Vec<&[u8]> =
+                    identifiers.iter().map(|id| id.as_bytes()).collect();
+                assert_eq!(names, expected);
+                for (member, identifier) in identifiers.iter().enumerate() {
+                    let mut entry = archive.jump_to_entry(member).unwrap();
+                    let data = entry.read_all().unwrap();
+                    assert_eq!(data, identifier.as_bytes());
+                }
+                let symbols: Vec<(Vec<u8>, u64)> = archive
+                    .symbols()
+                    .unwrap()
+                    .map(|(symbol, offset)| (symbol.to_vec(), offset))
+                    .collect();
+                if source == Variant::Common || target == Variant::Common {
+                    assert!(symbols.is_empty());
+                } else {
+                    let expected: Vec<(Vec<u8>, u64)> = index
+                        .iter()
+                        .enumerate()
+                        .map(|(member, &(_, offset, _))| {
+                            (format!("sym_{}", member).into_bytes(), offset)
+                        })
+                        .collect();
+                    assert_eq!(symbols, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn convert_to_aix_fails() {
+        let input = convert_source(Variant::BSD, &["foo.o"]);
+        let mut archive = Archive::new_seekable(Cursor::new(input));
+        let error =
+            convert(&mut archive, Variant::AIX, Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
 }
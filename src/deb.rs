@@ -0,0 +1,62 @@
+use std::io::{Read, Seek};
+
+use crate::{Archive, Entry, Result};
+
+/// The compression suffixes that `dpkg` accepts on the names of the control
+/// and data members of a Debian package.
+const DEB_COMPRESSION_SUFFIXES: [&str; 6] =
+    ["", ".gz", ".xz", ".zst", ".bz2", ".lzma"];
+
+/// One of the well-known members of a Debian package (`.deb`) file, for use
+/// with `Archive::deb_member()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebMember {
+    /// The `debian-binary` member, giving the package format version.
+    Binary,
+    /// The `control.tar` member (which is usually compressed, e.g. as
+    /// `control.tar.xz`), holding the package metadata and scripts.
+    Control,
+    /// The `data.tar` member (which is usually compressed, e.g. as
+    /// `data.tar.zst`), holding the files installed by the package.
+    Data,
+}
+
+impl DebMember {
+    /// Returns true if the given entry identifier is a name that this
+    /// member can have: `debian-binary` for `DebMember::Binary`, and
+    /// otherwise the tarball name, either uncompressed or with one of the
+    /// suffixes `.gz`, `.xz`, `.zst`, `.bz2` or `.lzma`.
+    pub fn matches(self, identifier: &[u8]) -> bool {
+        let stem: &[u8] = match self {
+            DebMember::Binary => return identifier == b"debian-binary",
+            DebMember::Control => b"control.tar",
+            DebMember::Data => b"data.tar",
+        };
+        identifier.starts_with(stem)
+            && DEB_COMPRESSION_SUFFIXES
+                .iter()
+                .any(|suffix| &identifier[stem.len()..] == suffix.as_bytes())
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Scans the archive and jumps to the first entry that is the given
+    /// member of a Debian package, whatever compression its name indicates
+    /// (see `DebMember::matches()`).  Returns `Ok(None)` if there is no such
+    /// entry.  The entry's data is returned as stored, without being
+    /// decompressed; the compression can be told from
+    /// `Header::identifier()`.
+    pub fn deb_member(
+        &mut self,
+        which: DebMember,
+    ) -> Result<Option<Entry<'_, R>>> {
+        let index = self
+            .index()?
+            .iter()
+            .position(|(identifier, _, _)| which.matches(identifier));
+        match index {
+            Some(index) => self.jump_to_entry(index).map(Some),
+            None => Ok(None),
+        }
+    }
+}
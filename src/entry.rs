@@ -1,18 +1,32 @@
 use std::cmp;
-use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::fs::File;
+use std::io::{self, BufRead, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
+use crate::error::{Error as ArError, Result};
 use crate::header::Header;
 
 /// Representation of an archive entry.
 ///
 /// `Entry` objects implement the `Read` trait, and can be used to extract the
 /// data from this archive entry.  If the underlying reader supports the `Seek`
-/// trait, then the `Entry` object supports `Seek` as well.
+/// or `BufRead` traits, then the `Entry` object supports them as well.
+///
+/// An entry can be dropped without reading all of its data.  Dropping it
+/// doesn't read anything: the rest of the data is only skipped over (by
+/// seeking, if the reader supports it) when the archive is next asked for an
+/// entry, so abandoning an archive in the middle of an entry costs nothing.
 pub struct Entry<'a, R: 'a + Read> {
     pub(crate) header: &'a Header,
+    pub(crate) member_offset: u64,
+    pub(crate) data_offset: u64,
     pub(crate) reader: &'a mut R,
     pub(crate) length: u64,
     pub(crate) position: u64,
+    // Where the data starts in the underlying stream, once known.
+    pub(crate) stream_data_start: Option<u64>,
+    pub(crate) thin: bool,
+    pub(crate) unread: &'a mut u64,
 }
 
 impl<'a, R: 'a + Read> Entry<'a, R> {
@@ -20,10 +34,142 @@ impl<'a, R: 'a + Read> Entry<'a, R> {
     pub fn header(&self) -> &Header {
         self.header
     }
+
+    /// Returns the offset of this entry's header from the start of the
+    /// archive.  This is the offset that archive symbol tables use to refer
+    /// to the member that defines a symbol.
+    pub fn member_offset(&self) -> u64 {
+        self.member_offset
+    }
+
+    /// Returns the offset of this entry's data from the start of the archive
+    /// (after the header and, for BSD long filenames, the identifier).
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// Returns the number of bytes of this entry's data that have been read
+    /// (or seeked past) so far.  This is the same as the entry's current
+    /// position as reported by `Seek`, but doesn't need the underlying reader
+    /// to be seekable.  The entry's size is always `position()` plus
+    /// `bytes_remaining()`.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the number of bytes of this entry's data that haven't been
+    /// read yet.
+    pub fn bytes_remaining(&self) -> u64 {
+        self.length - self.position
+    }
+
+    /// Moves `n` bytes forward in this entry's data, returning the new
+    /// position.  Unlike `Seek`, this doesn't need the underlying reader to
+    /// be seekable, since the data skipped over is read and discarded, so it
+    /// lets a streaming reader skip part of an entry; going backwards still
+    /// needs `Seek`.  Returns an error if that would go past the end of the
+    /// entry, or if the archive ends first.
+    pub fn seek_forward(&mut self, n: u64) -> Result<u64> {
+        if n > self.bytes_remaining() {
+            let msg = format!(
+                "Invalid seek to position past end of entry ({} vs. {})",
+                u128::from(self.position) + u128::from(n),
+                self.length
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        let skipped = io::copy(&mut self.by_ref().take(n), &mut io::sink())?;
+        if skipped < n {
+            let expected = self.length;
+            let actual = self.position;
+            return Err(ArError::TruncatedData { expected, actual });
+        }
+        Ok(self.position)
+    }
+
+    /// Reads the rest of this entry's data into a new vector, which is
+    /// allocated up front to hold exactly `bytes_remaining()` bytes.  Returns
+    /// an error if the archive ends before all of the data has been read.
+    pub fn read_all(&mut self) -> Result<Vec<u8>> {
+        let expected = self.bytes_remaining();
+        let mut buffer = Vec::new();
+        buffer.try_reserve_exact(expected as usize).map_err(|_| {
+            let msg = format!(
+                "Entry is too large to read into memory ({})",
+                expected
+            );
+            Error::new(ErrorKind::OutOfMemory, msg)
+        })?;
+        self.read_to_end(&mut buffer)?;
+        if (buffer.len() as u64) < expected {
+            let actual = buffer.len() as u64;
+            return Err(ArError::TruncatedData { expected, actual });
+        }
+        Ok(buffer)
+    }
+
+    /// Opens the external file that this member of a GNU thin archive refers
+    /// to.  The identifier of a thin member is a path relative to the
+    /// directory containing the archive, which should be passed as
+    /// `base_dir`.  Returns an error if this entry is not from a thin archive.
+    pub fn open_thin_member<P: AsRef<Path>>(
+        &self,
+        base_dir: P,
+    ) -> Result<File> {
+        if !self.thin {
+            let msg = "Entry is not a member of a thin archive";
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        let path = bytes_to_path(self.header.identifier())?;
+        File::open(base_dir.as_ref().join(path)).map_err(ArError::from)
+    }
+
+    /// Wraps this entry so that all of the data read from it is also written
+    /// to `hasher`, which lets a digest of the entry's data be computed in
+    /// the same pass that processes it.  Any hasher that implements `Write`
+    /// (as those of the `digest` crate family do) can be used.
+    pub fn hashed<H: Write>(self, hasher: H) -> HashedEntry<'a, R, H> {
+        HashedEntry { entry: self, hasher }
+    }
+}
+
+/// An archive entry whose data is fed through a hasher as it is read,
+/// created by `Entry::hashed()`.
+pub struct HashedEntry<'a, R: 'a + Read, H: Write> {
+    entry: Entry<'a, R>,
+    hasher: H,
+}
+
+impl<'a, R: 'a + Read, H: Write> HashedEntry<'a, R, H> {
+    /// Returns the header for this archive entry.
+    pub fn header(&self) -> &Header {
+        self.entry.header()
+    }
+
+    /// Feeds the rest of the entry's data (if any) through the hasher, and
+    /// then returns the hasher, ready to be finalized.  Returns an error if
+    /// the archive ends before all of the data has been read.
+    pub fn finalize_hash(mut self) -> Result<H> {
+        let expected = self.entry.bytes_remaining();
+        let actual = io::copy(&mut self, &mut io::sink())?;
+        if actual < expected {
+            return Err(ArError::TruncatedData { expected, actual });
+        }
+        self.hasher.flush()?;
+        Ok(self.hasher)
+    }
+}
+
+impl<'a, R: 'a + Read, H: Write> Read for HashedEntry<'a, R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.entry.read(buf)?;
+        self.hasher.write_all(&buf[..bytes_read])?;
+        Ok(bytes_read)
+    }
 }
 
 impl<'a, R: 'a + Read> Read for Entry<'a, R> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         debug_assert!(self.position <= self.length);
         if self.position == self.length {
             return Ok(0);
@@ -37,16 +183,38 @@ impl<'a, R: 'a + Read> Read for Entry<'a, R> {
     }
 }
 
+impl<'a, R: 'a + BufRead> BufRead for Entry<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        debug_assert!(self.position <= self.length);
+        if self.position == self.length {
+            return Ok(&[]);
+        }
+        let buf = self.reader.fill_buf()?;
+        let max_len =
+            cmp::min(self.length - self.position, buf.len() as u64) as usize;
+        Ok(&buf[0..max_len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = cmp::min(self.length - self.position, amt as u64);
+        self.reader.consume(amt as usize);
+        self.position += amt;
+    }
+}
+
 impl<'a, R: 'a + Read + Seek> Seek for Entry<'a, R> {
-    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-        let delta = match pos {
-            SeekFrom::Start(offset) => offset as i64 - self.position as i64,
-            SeekFrom::End(offset) => {
-                self.length as i64 + offset - self.position as i64
-            }
-            SeekFrom::Current(delta) => delta,
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // Asking for the current position doesn't need to move the reader.
+        if let SeekFrom::Current(0) = pos {
+            return Ok(self.position);
+        }
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(delta) => (self.length, delta),
+            SeekFrom::Current(delta) => (self.position, delta),
         };
-        let new_position = self.position as i64 + delta;
+        // This can't overflow, whatever the offsets, unlike `u64` or `i64`.
+        let new_position = i128::from(base) + i128::from(delta);
         if new_position < 0 {
             let msg = format!(
                 "Invalid seek to negative position ({})",
@@ -54,15 +222,34 @@ impl<'a, R: 'a + Read + Seek> Seek for Entry<'a, R> {
             );
             return Err(Error::new(ErrorKind::InvalidInput, msg));
         }
-        let new_position = new_position as u64;
-        if new_position > self.length {
+        if new_position > i128::from(self.length) {
             let msg = format!(
                 "Invalid seek to position past end of entry ({} vs. {})",
                 new_position, self.length
             );
             return Err(Error::new(ErrorKind::InvalidInput, msg));
         }
-        self.reader.seek(SeekFrom::Current(delta))?;
+        let new_position = new_position as u64;
+        // The archive's offsets are from where it starts, which needn't be
+        // the start of the stream, so the first seek finds where the data
+        // starts in the stream from where the reader is.
+        let data_start = match self.stream_data_start {
+            Some(data_start) => data_start,
+            None => {
+                let current = self.reader.stream_position()?;
+                let data_start =
+                    current.checked_sub(self.position).ok_or_else(|| {
+                        let msg = "Underlying reader is before the entry";
+                        Error::new(ErrorKind::InvalidInput, msg)
+                    })?;
+                self.stream_data_start = Some(data_start);
+                data_start
+            }
+        };
+        // After that, seek to an absolute offset, rather than by `delta`, so
+        // that this works even if the underlying reader isn't where we left
+        // it.
+        self.reader.seek(SeekFrom::Start(data_start + new_position))?;
         self.position = new_position;
         Ok(self.position)
     }
@@ -70,10 +257,23 @@ impl<'a, R: 'a + Read + Seek> Seek for Entry<'a, R> {
 
 impl<'a, R: 'a + Read> Drop for Entry<'a, R> {
     fn drop(&mut self) {
-        if self.position < self.length {
-            // Consume the rest of the data in this entry.
-            let mut remaining = self.reader.take(self.length - self.position);
-            let _ = io::copy(&mut remaining, &mut io::sink());
-        }
+        // The archive skips the rest of the data in this entry (by seeking,
+        // if it can) before reading anything else.
+        *self.unread = self.length - self.position;
     }
 }
+
+#[cfg(unix)]
+pub(crate) fn bytes_to_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn bytes_to_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    let utf8 = std::str::from_utf8(bytes).map_err(|_| {
+        Error::new(ErrorKind::InvalidData, "Non-UTF8 file name")
+    })?;
+    Ok(PathBuf::from(utf8))
+}
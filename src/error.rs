@@ -1,5 +1,283 @@
+use std::error;
+use std::fmt;
 use std::io;
 
+/// The errors that can occur while reading or writing an archive.
+///
+/// The methods of this crate return `ar::Result`, so that the structural
+/// problems found in an archive can be told apart from one another, and from
+/// failures of the underlying reader or writer, without looking at their
+/// messages:
+///
+/// ```
+/// use ar::Archive;
+/// let mut archive = Archive::new("!<junk>\n".as_bytes());
+/// match archive.next_entry() {
+///     Some(Err(ar::Error::BadMagic)) => {}
+///     _ => panic!("expected an error"),
+/// };
+/// ```
+///
+/// An error from reading or writing a particular member is returned as
+/// `Error::InMember`, which says which member it occurred in, e.g. "while
+/// reading member "foo.o" at offset 1234: invalid padding byte (42)", so that
+/// the problem can be found; `Error::without_context()` returns the error
+/// itself.  The `Read` and `Write` implementations of this crate (such as that
+/// of `Entry`) must return an `io::Error`, which carries the `ar::Error` as
+/// its payload; it can be recovered with `ar::Error::from`.
+#[non_exhaustive]
+pub enum Error {
+    /// The data doesn't start with an archive global header.
+    BadMagic,
+    /// The archive ended in the middle of an entry header.
+    TruncatedHeader,
+    /// The archive ended in the middle of a BSD extended entry identifier.
+    TruncatedIdentifier,
+    /// The archive ended before all of an entry's data.
+    TruncatedData {
+        /// The size of the data given in the entry header.
+        expected: u64,
+        /// The number of bytes of data actually present.
+        actual: u64,
+    },
+    /// A numeric field in an entry header couldn't be parsed.
+    InvalidField {
+        /// The name of the field.
+        field: &'static str,
+        /// The contents of the field (lossily converted to UTF-8).
+        value: String,
+    },
+    /// An entry's size is smaller than its BSD extended identifier.
+    InvalidSize {
+        /// The size given in the entry header.
+        size: u64,
+        /// The (padded) length of the extended identifier.
+        identifier_length: u64,
+    },
+    /// An entry's size is larger than the limit set with
+    /// `Archive::set_size_limit()`.
+    SizeLimitExceeded {
+        /// The size given in the entry header.
+        size: u64,
+        /// The maximum size allowed.
+        limit: u64,
+    },
+    /// The padding byte after an entry with odd-sized data wasn't a newline
+    /// (or a NUL byte).
+    InvalidPadding(u8),
+    /// A GNU entry header refers to a name outside of the GNU name table.
+    NameNotInGnuTable {
+        /// The offset into the name table given in the entry header.
+        index: usize,
+    },
+    /// A value is too large for its fixed-width field in an entry header.
+    FieldOverflow {
+        /// The name of the field.
+        field: &'static str,
+        /// The value that was to be written, as it would be formatted.
+        value: String,
+        /// The maximum number of digits the field can hold.
+        width: usize,
+    },
+    /// An entry was appended to a `GnuBuilder` with an identifier that wasn't
+    /// in the list of identifiers passed to `GnuBuilder::new()`.
+    UndeclaredIdentifier(Vec<u8>),
+    /// An entry was appended to a builder with duplicate checking enabled
+    /// (see `Builder::set_dedup()`) with the same identifier as an entry
+    /// that was already appended.
+    DuplicateIdentifier(Vec<u8>),
+    /// An error found while validating an archive, along with where it was
+    /// found.
+    InvalidMember {
+        /// The offset in the archive of the offending member's header (or,
+        /// for a bad padding byte, of that byte).
+        offset: u64,
+        /// The error that was found.
+        error: Box<Error>,
+    },
+    /// An error that occurred while reading or writing a particular member
+    /// of the archive.
+    InMember {
+        /// What was being done to the member: `"reading"` or `"writing"`.
+        action: &'static str,
+        /// The member's identifier, unless the error occurred before its
+        /// header was parsed.
+        identifier: Option<Vec<u8>>,
+        /// The offset in the archive of the member's header, unless it isn't
+        /// known yet (as for a member that a builder is buffering).
+        offset: Option<u64>,
+        /// The error that occurred.
+        error: Box<Error>,
+    },
+    /// An error from the underlying reader or writer, or (with a kind of
+    /// `io::ErrorKind::InvalidInput`) from a method being called with
+    /// arguments that it can't accept.
+    Io(io::Error),
+}
+
+/// A specialized `Result` type for the operations of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns the `io::ErrorKind` used when this error is converted into an
+    /// `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::TruncatedHeader
+            | Error::TruncatedIdentifier
+            | Error::TruncatedData { .. } => io::ErrorKind::UnexpectedEof,
+            Error::UndeclaredIdentifier(_) | Error::DuplicateIdentifier(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            Error::InvalidMember { ref error, .. }
+            | Error::InMember { ref error, .. } => error.kind(),
+            Error::Io(ref error) => error.kind(),
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+
+    /// Returns this error without the context given by `Error::InMember`,
+    /// for matching on the problem itself.
+    pub fn without_context(self) -> Error {
+        match self {
+            Error::InMember { error, .. } => *error,
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::BadMagic => {
+                f.write_str("Not an archive file (invalid global header)")
+            }
+            Error::TruncatedHeader => f.write_str(
+                "unexpected EOF in the middle of archive entry header",
+            ),
+            Error::TruncatedIdentifier => f.write_str(
+                "unexpected EOF in the middle of extended entry identifier",
+            ),
+            Error::TruncatedData { expected, actual } => write!(
+                f,
+                "unexpected EOF in the middle of archive entry data \
+                 ({} of {} bytes present)",
+                actual, expected
+            ),
+            Error::InvalidField { field, ref value } => write!(
+                f,
+                "Invalid {} field in entry header ({:?})",
+                field, value
+            ),
+            Error::InvalidSize { size, identifier_length } => write!(
+                f,
+                "Entry size ({}) smaller than extended entry identifier \
+                 length ({})",
+                size, identifier_length
+            ),
+            Error::SizeLimitExceeded { size, limit } => write!(
+                f,
+                "Entry size ({}) exceeds the size limit ({})",
+                size, limit
+            ),
+            Error::InvalidPadding(byte) => {
+                write!(f, "invalid padding byte ({})", byte)
+            }
+            Error::NameNotInGnuTable { .. } => {
+                f.write_str("GNU filename index out of range")
+            }
+            Error::FieldOverflow { field, ref value, width } => write!(
+                f,
+                "Value {} is too large for the {} field in entry header \
+                 (max {} digits)",
+                value, field, width
+            ),
+            Error::UndeclaredIdentifier(ref identifier) => write!(
+                f,
+                "Identifier {:?} was not in the list of identifiers passed \
+                 to GnuBuilder::new()",
+                String::from_utf8_lossy(identifier)
+            ),
+            Error::DuplicateIdentifier(ref identifier) => write!(
+                f,
+                "Identifier {:?} was already used by another entry",
+                String::from_utf8_lossy(identifier)
+            ),
+            Error::InvalidMember { offset, ref error } => {
+                write!(
+                    f,
+                    "invalid archive member at offset {}: {}",
+                    offset, error
+                )
+            }
+            Error::InMember { action, ref identifier, offset, ref error } => {
+                write!(f, "while {} ", action)?;
+                match *identifier {
+                    Some(ref identifier) => write!(
+                        f,
+                        "member {:?}",
+                        String::from_utf8_lossy(identifier)
+                    )?,
+                    None => f.write_str("the member")?,
+                }
+                if let Some(offset) = offset {
+                    write!(f, " at offset {}", offset)?;
+                }
+                write!(f, ": {}", error)
+            }
+            Error::Io(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    /// Formats the error like an `io::Error` with a custom message, so that
+    /// the message is still shown by `unwrap()` and `expect()`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => f.debug_tuple("Io").field(error).finish(),
+            _ => f
+                .debug_struct("Error")
+                .field("kind", &self.kind())
+                .field("message", &self.to_string())
+                .finish(),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::InvalidMember { ref error, .. }
+            | Error::InMember { ref error, .. } => Some(error.as_ref()),
+            Error::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Recovers the `ar::Error` carried by an `io::Error` (such as one
+    /// returned by reading an `Entry`), or wraps any other `io::Error` as
+    /// `Error::Io`.
+    fn from(error: io::Error) -> Error {
+        if error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = error.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+        Error::Io(error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
 pub(crate) fn annotate(error: io::Error, msg: &str) -> io::Error {
     let kind = error.kind();
     if let Some(inner) = error.into_inner() {
@@ -8,3 +286,29 @@ pub(crate) fn annotate(error: io::Error, msg: &str) -> io::Error {
         io::Error::new(kind, msg)
     }
 }
+
+/// Wraps an error in `Error::InMember`, so that its message says which member
+/// it occurred in (e.g. "while reading member \"foo.o\" at offset 1234"),
+/// keeping its kind.  The identifier isn't known before a member's header has
+/// been parsed, and the offset isn't known for a member that a builder is
+/// buffering.  An error that already names its member is returned as-is.
+pub(crate) fn member_context(
+    error: io::Error,
+    action: &'static str,
+    identifier: Option<&[u8]>,
+    offset: Option<u64>,
+) -> io::Error {
+    let error = Error::from(error);
+    if let Error::InMember { .. } = error {
+        return error.into();
+    }
+    io::Error::new(
+        error.kind(),
+        Error::InMember {
+            action,
+            identifier: identifier.map(<[u8]>::to_vec),
+            offset,
+            error: Box::new(error),
+        },
+    )
+}
@@ -1,21 +1,57 @@
+use std::borrow::Cow;
+use std::cmp;
 use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
 use std::fs::Metadata;
-use std::io::{self, Error, ErrorKind, Read, Result, Write};
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::path::Path;
 use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
 use crate::archive::{
     Variant, BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID, BSD_SYMBOL_LOOKUP_TABLE_ID,
-    GNU_NAME_TABLE_ID, GNU_SYMBOL_LOOKUP_TABLE_ID,
+    GNU_NAME_TABLE_ID, GNU_SYMBOL_LOOKUP_TABLE_64_ID,
+    GNU_SYMBOL_LOOKUP_TABLE_ID,
 };
-use crate::error::annotate;
+use crate::error::{annotate, Error as ArError, Result};
+
+pub(crate) const ENTRY_HEADER_LEN: usize = 60;
+
+/// The largest owner or group ID that fits in an entry header.
+const MAX_ID: u32 = 999_999;
+
+/// The length of an AIX big archive's fixed-length header, including the
+/// `<bigaf>\n` magic string.
+pub(crate) const AIX_FIXED_HEADER_LEN: u64 = 128;
+
+const AIX_ENTRY_HEADER_LEN: usize = 112;
 
-const ENTRY_HEADER_LEN: usize = 60;
+/// The way an entry header stores the entry's identifier, as reported by
+/// `Header::parse()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParsedKind {
+    /// The identifier is stored directly in the header's identifier field.
+    Plain,
+    /// The identifier is stored in the GNU archive name table, at the given
+    /// offset (a GNU `/offset` identifier field).
+    GnuNameTable(usize),
+    /// The identifier, of the given (padded) length, immediately follows
+    /// the header, and is counted in the header's size field (a BSD
+    /// `#1/length` identifier field).
+    BsdExtended(u64),
+}
 
 /// Representation of an archive entry header.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// The `Debug` output shows the identifier as a (lossily converted) string
+/// and the mode in octal, rather than as raw bytes and a decimal number.
+#[derive(Clone, Eq, PartialEq)]
 pub struct Header {
     identifier: Vec<u8>,
     mtime: u64,
@@ -32,6 +68,34 @@ impl Header {
         Header { identifier, mtime: 0, uid: 0, gid: 0, mode: 0, size }
     }
 
+    /// Creates a header with the given file identifier and size, like
+    /// `Header::new()`, but accepting the identifier as anything that
+    /// converts into bytes, such as a `&str`, `String`, or `&[u8]`, e.g.
+    /// `Header::with_name("foo.txt", 7)`.
+    pub fn with_name<N: Into<Vec<u8>>>(name: N, size: u64) -> Header {
+        Header::new(name.into(), size)
+    }
+
+    /// Creates a header with the given size, and the file name component of
+    /// the given path as its identifier (as `Builder::append_path()` uses),
+    /// with all other fields set to zero.  Returns an error if the path
+    /// doesn't end in a file name, or (on non-Unix systems) if the file name
+    /// isn't valid UTF-8.
+    pub fn from_path_name<P: AsRef<Path>>(
+        path: P,
+        size: u64,
+    ) -> Result<Header> {
+        let identifier = file_name_identifier(path.as_ref())?;
+        Ok(Header::new(identifier, size))
+    }
+
+    /// Starts building a header with the given file identifier and size.
+    /// The other fields can then be set in a chain of calls, e.g.
+    /// `Header::builder(name, size).mtime(t).uid(501).mode(0o644).build()`.
+    pub fn builder(identifier: Vec<u8>, size: u64) -> HeaderBuilder {
+        HeaderBuilder { header: Header::new(identifier, size) }
+    }
+
     /// Creates a header with the given file identifier and all other fields
     /// set from the given filesystem metadata.
     #[cfg(unix)]
@@ -46,9 +110,129 @@ impl Header {
         }
     }
 
+    /// Creates a header with the given file identifier and all other fields
+    /// set from the given filesystem metadata.  Since only Unix systems have
+    /// owner/group IDs and mode bits, these are set to zero and `0o644`.
     #[cfg(not(unix))]
     pub fn from_metadata(identifier: Vec<u8>, meta: &Metadata) -> Header {
-        Header::new(identifier, meta.len())
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        Header {
+            identifier,
+            mtime,
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            size: meta.len(),
+        }
+    }
+
+    /// Parses a standalone entry header.  For a `ParsedKind::Plain`
+    /// identifier, the returned header's identifier has its padding (and
+    /// any GNU-style trailing `/`) removed.  Otherwise, the real identifier
+    /// isn't in the header itself, so the returned header's identifier and
+    /// size are the raw contents of those fields, for the caller to resolve.
+    pub fn parse(
+        buffer: &[u8; ENTRY_HEADER_LEN],
+    ) -> Result<(Header, ParsedKind)> {
+        if &buffer[58..60] != b"`\n" {
+            return Err(invalid_field("terminator", &buffer[58..60]).into());
+        }
+        let mut identifier = buffer[0..16].to_vec();
+        while identifier.last() == Some(&b' ') {
+            identifier.pop();
+        }
+        let size = parse_number("file size", &buffer[48..58], 10, false)?;
+        // The other fields of the GNU special entries may be left blank.
+        if is_gnu_symbol_table_id(&identifier)
+            || identifier == GNU_NAME_TABLE_ID.as_bytes()
+        {
+            return Ok((Header::new(identifier, size), ParsedKind::Plain));
+        }
+        let kind = if identifier.starts_with(b"#1/") {
+            let length = parse_number(
+                "BSD filename length",
+                &buffer[3..16],
+                10,
+                false,
+            )?;
+            ParsedKind::BsdExtended(length)
+        } else if identifier.starts_with(b"/") {
+            let offset =
+                parse_number("GNU filename index", &buffer[1..16], 10, false)?;
+            ParsedKind::GnuNameTable(offset as usize)
+        } else {
+            if identifier.ends_with(b"/") {
+                identifier.pop();
+            }
+            ParsedKind::Plain
+        };
+        let mtime = parse_number_permitting_minus_one(
+            "timestamp",
+            &buffer[16..28],
+            10,
+            false,
+        )?;
+        let uid = parse_number_permitting_empty(
+            "owner ID",
+            &buffer[28..34],
+            10,
+            false,
+        )? as u32;
+        let gid = parse_number_permitting_empty(
+            "group ID",
+            &buffer[34..40],
+            10,
+            false,
+        )? as u32;
+        let mode =
+            parse_number("file mode", &buffer[40..48], 8, false)? as u32;
+        let header = Header { identifier, mtime, uid, gid, mode, size };
+        Ok((header, kind))
+    }
+
+    /// Serializes this header as the 60-byte entry header that a builder for
+    /// the given variant would write.  For the BSD variant, an identifier
+    /// that is longer than 16 bytes or contains a space is written as a
+    /// `#1/length` field, and the identifier itself (padded to a multiple of
+    /// four bytes and counted in the size field) must then follow the
+    /// header.  Returns an error if the identifier can't be stored in the
+    /// header itself for the common variant, or needs the name table for the
+    /// GNU variant (that is, is longer than 15 bytes), or if a numeric field
+    /// is too large.  Writing AIX big archive headers isn't supported, so
+    /// that variant is always an error.
+    pub fn to_bytes(
+        &self,
+        variant: Variant,
+    ) -> Result<[u8; ENTRY_HEADER_LEN]> {
+        let fits = match variant {
+            Variant::Common => {
+                self.identifier.len() <= 16 && !self.identifier.contains(&b' ')
+            }
+            Variant::BSD => true,
+            Variant::GNU => self.identifier.len() <= 15,
+            Variant::AIX => false,
+        };
+        if !fits {
+            let msg = format!(
+                "Identifier {:?} can't be stored in a {:?} entry header",
+                String::from_utf8_lossy(&self.identifier),
+                variant
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        let mut bytes = Vec::with_capacity(ENTRY_HEADER_LEN);
+        if variant == Variant::GNU {
+            self.write_gnu(&mut bytes, &HashMap::new())?;
+        } else {
+            self.write(&mut bytes)?;
+        }
+        let mut buffer = [0; ENTRY_HEADER_LEN];
+        buffer.copy_from_slice(&bytes[..ENTRY_HEADER_LEN]);
+        Ok(buffer)
     }
 
     /// Returns the file identifier.
@@ -56,6 +240,36 @@ impl Header {
         &self.identifier
     }
 
+    /// Returns the file identifier as a path, without any UTF-8 conversion.
+    #[cfg(unix)]
+    pub fn identifier_path(&self) -> &Path {
+        Path::new(OsStr::from_bytes(&self.identifier))
+    }
+
+    /// Returns the file identifier as a string, replacing any invalid UTF-8
+    /// sequences with U+FFFD REPLACEMENT CHARACTER.
+    pub fn identifier_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.identifier)
+    }
+
+    /// Returns true if this is the header of a symbol table member, that is,
+    /// if its identifier is exactly `/` or `/SYM64/` (the GNU variant's
+    /// 32-bit and 64-bit symbol tables) or `__.SYMDEF` or `__.SYMDEF SORTED`
+    /// (the BSD variant's).  `Archive::next_entry()` never returns such
+    /// members, but they can be found among raw headers, e.g. from
+    /// `Header::parse()`.
+    pub fn is_symbol_table(&self) -> bool {
+        is_gnu_symbol_table_id(&self.identifier)
+            || is_bsd_symbol_table_id(&self.identifier)
+    }
+
+    /// Returns true if this is the header of a GNU name table member, that
+    /// is, if its identifier is exactly `//`.  Like symbol tables, such
+    /// members are never returned by `Archive::next_entry()`.
+    pub fn is_name_table(&self) -> bool {
+        self.identifier == GNU_NAME_TABLE_ID.as_bytes()
+    }
+
     /// Sets the file identifier.
     pub fn set_identifier(&mut self, identifier: Vec<u8>) {
         self.identifier = identifier;
@@ -71,6 +285,24 @@ impl Header {
         self.mtime = mtime;
     }
 
+    /// Returns the last modification time as a `SystemTime`, or `None` if it
+    /// can't be represented as one on this platform.
+    pub fn modified(&self) -> Option<SystemTime> {
+        UNIX_EPOCH.checked_add(Duration::from_secs(self.mtime))
+    }
+
+    /// Sets the last modification time from a `SystemTime`.  The mtime field
+    /// only holds whole seconds, so any fraction of a second is discarded,
+    /// always rounding toward the epoch (e.g. 1.999 seconds after it is
+    /// stored as 1); `modified()` then returns the time truncated to the
+    /// second.  Times before the Unix epoch are clamped to the epoch, since
+    /// the mtime field can't represent them.
+    pub fn set_modified(&mut self, time: SystemTime) {
+        self.mtime = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+    }
+
     /// Returns the value of the owner's user ID field.
     pub fn uid(&self) -> u32 {
         self.uid
@@ -91,11 +323,27 @@ impl Header {
         self.gid = gid;
     }
 
-    /// Returns the mode bits for this file.
+    /// Returns the mode bits for this file, as stored in the header: both the
+    /// file type bits and the permission bits (e.g. `0o100644`).  Most
+    /// archive members are regular files, but the field is kept verbatim,
+    /// whatever it contains.
     pub fn mode(&self) -> u32 {
         self.mode
     }
 
+    /// Returns only the permission bits of the mode (`mode & 0o7777`),
+    /// including the setuid, setgid, and sticky bits, e.g. `0o644`.
+    pub fn permissions(&self) -> u32 {
+        self.mode & 0o7777
+    }
+
+    /// Returns only the file type bits of the mode (`mode & 0o170000`), e.g.
+    /// `0o100000` for a regular file.  This is zero if the archive was
+    /// written by a tool that only records permissions.
+    pub fn file_type(&self) -> u32 {
+        self.mode & 0o170000
+    }
+
     /// Sets the mode bits for this file.
     pub fn set_mode(&mut self, mode: u32) {
         self.mode = mode;
@@ -111,13 +359,64 @@ impl Header {
         self.size = size;
     }
 
+    /// Returns an object that formats this header as a line of a verbose
+    /// listing, in the style of `ar tv`: the permission bits of the mode (as
+    /// in `rw-r--r--`), the owner and group IDs, the size, the modification
+    /// time, and the identifier.  The modification time is shown in UTC.
+    ///
+    /// ```
+    /// let header = ar::Header::builder(b"foo.o".to_vec(), 1024)
+    ///     .mode(0o100644)
+    ///     .uid(501)
+    ///     .gid(20)
+    ///     .mtime(1487552916)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     header.display_long().to_string(),
+    ///     "rw-r--r-- 501/20     1024 Feb 20 01:08 2017 foo.o"
+    /// );
+    /// ```
+    pub fn display_long(&self) -> impl fmt::Display + '_ {
+        DisplayLong(self)
+    }
+
+    /// Returns a copy of this header with the metadata fields normalized for
+    /// a deterministic archive.
+    pub(crate) fn to_deterministic(&self) -> Header {
+        Header {
+            identifier: self.identifier.clone(),
+            mtime: 0,
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            size: self.size,
+        }
+    }
+
+    /// Returns a copy of this header with its owner and group IDs limited to
+    /// the largest value that their six-digit fields can hold.
+    pub(crate) fn to_clamped_ids(&self) -> Header {
+        Header {
+            uid: cmp::min(self.uid, MAX_ID),
+            gid: cmp::min(self.gid, MAX_ID),
+            ..self.clone()
+        }
+    }
+
     /// Parses and returns the next header and its length.  Returns `Ok(None)`
-    /// if we are at EOF.
+    /// if we are at EOF.  If `check_terminator` is true, also rejects headers
+    /// that don't end with the two-byte "`\n" terminator.  A declared size
+    /// over `size_limit` is rejected before anything further is read, so
+    /// that a BSD identifier or GNU name table of that size isn't allocated.
     pub(crate) fn read<R>(
         reader: &mut R,
         variant: &mut Variant,
         name_table: &mut Vec<u8>,
-    ) -> Result<Option<(Header, u64)>>
+        check_terminator: bool,
+        lenient: bool,
+        size_limit: Option<u64>,
+    ) -> io::Result<Option<(Header, u64)>>
     where
         R: Read,
     {
@@ -128,47 +427,53 @@ impl Header {
         } else if bytes_read < buffer.len() {
             if let Err(error) = reader.read_exact(&mut buffer[bytes_read..]) {
                 if error.kind() == ErrorKind::UnexpectedEof {
-                    let msg = "unexpected EOF in the middle of archive entry \
-                               header";
-                    return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+                    return Err(ArError::TruncatedHeader.into());
                 } else {
                     let msg = "failed to read archive entry header";
                     return Err(annotate(error, msg));
                 }
             }
         }
+        if check_terminator && &buffer[58..60] != b"`\n" {
+            return Err(invalid_field("terminator", &buffer[58..60]));
+        }
         let mut identifier = buffer[0..16].to_vec();
         while identifier.last() == Some(&b' ') {
             identifier.pop();
         }
-        let mut size = parse_number("file size", &buffer[48..58], 10)?;
+        let mut size =
+            parse_number("file size", &buffer[48..58], 10, lenient)?;
+        if let Some(limit) = size_limit {
+            if size > limit {
+                return Err(ArError::SizeLimitExceeded { size, limit }.into());
+            }
+        }
         let mut header_len = ENTRY_HEADER_LEN as u64;
         if *variant != Variant::BSD && identifier.starts_with(b"/") {
             *variant = Variant::GNU;
-            if identifier == GNU_SYMBOL_LOOKUP_TABLE_ID {
+            if is_gnu_symbol_table_id(&identifier) {
                 io::copy(&mut reader.by_ref().take(size), &mut io::sink())?;
                 return Ok(Some((Header::new(identifier, size), header_len)));
             } else if identifier == GNU_NAME_TABLE_ID.as_bytes() {
-                *name_table = vec![0; size as usize];
-                reader.read_exact(name_table as &mut [u8]).map_err(|err| {
-                    annotate(err, "failed to read name table")
-                })?;
+                // Read only as much as is present, rather than allocating
+                // the declared size up front, which may be enormous.
+                name_table.clear();
+                reader.by_ref().take(size).read_to_end(name_table).map_err(
+                    |err| annotate(err, "failed to read name table"),
+                )?;
+                if (name_table.len() as u64) < size {
+                    let kind = ErrorKind::UnexpectedEof;
+                    return Err(Error::new(kind, "failed to read name table"));
+                }
                 return Ok(Some((Header::new(identifier, size), header_len)));
             }
-            let start = parse_number("GNU filename index", &buffer[1..16], 10)?
-                as usize;
-            if start > name_table.len() {
-                let msg = "GNU filename index out of range";
-                return Err(Error::new(ErrorKind::InvalidData, msg));
-            }
-            let end = match name_table[start..]
-                .iter()
-                .position(|&ch| ch == b'/' || ch == b'\x00')
-            {
-                Some(len) => start + len,
-                None => name_table.len(),
-            };
-            identifier = name_table[start..end].to_vec();
+            let start = parse_number(
+                "GNU filename index",
+                &buffer[1..16],
+                10,
+                lenient,
+            )? as usize;
+            identifier = gnu_table_name(name_table, start)?.to_vec();
         } else if *variant != Variant::BSD && identifier.ends_with(b"/") {
             *variant = Variant::GNU;
             identifier.pop();
@@ -177,29 +482,44 @@ impl Header {
             "timestamp",
             &buffer[16..28],
             10,
+            lenient,
         )?;
         let uid = if *variant == Variant::GNU {
-            parse_number_permitting_empty("owner ID", &buffer[28..34], 10)?
+            parse_number_permitting_empty(
+                "owner ID",
+                &buffer[28..34],
+                10,
+                lenient,
+            )?
         } else {
-            parse_number("owner ID", &buffer[28..34], 10)?
+            parse_number("owner ID", &buffer[28..34], 10, lenient)?
         } as u32;
         let gid = if *variant == Variant::GNU {
-            parse_number_permitting_empty("group ID", &buffer[34..40], 10)?
+            parse_number_permitting_empty(
+                "group ID",
+                &buffer[34..40],
+                10,
+                lenient,
+            )?
         } else {
-            parse_number("group ID", &buffer[34..40], 10)?
+            parse_number("group ID", &buffer[34..40], 10, lenient)?
         } as u32;
-        let mode = parse_number("file mode", &buffer[40..48], 8)? as u32;
+        let mode =
+            parse_number("file mode", &buffer[40..48], 8, lenient)? as u32;
         if *variant != Variant::GNU && identifier.starts_with(b"#1/") {
             *variant = Variant::BSD;
-            let padded_length =
-                parse_number("BSD filename length", &buffer[3..16], 10)?;
+            let padded_length = parse_number(
+                "BSD filename length",
+                &buffer[3..16],
+                10,
+                lenient,
+            )?;
             if size < padded_length {
-                let msg = format!(
-                    "Entry size ({}) smaller than extended \
-                                   entry identifier length ({})",
-                    size, padded_length
-                );
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+                let error = ArError::InvalidSize {
+                    size,
+                    identifier_length: padded_length,
+                };
+                return Err(error.into());
             }
             size -= padded_length;
             header_len += padded_length;
@@ -210,9 +530,7 @@ impl Header {
                     reader.read_exact(&mut id_buffer[bytes_read..])
                 {
                     if error.kind() == ErrorKind::UnexpectedEof {
-                        let msg = "unexpected EOF in the middle of extended \
-                                   entry identifier";
-                        return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+                        return Err(ArError::TruncatedIdentifier.into());
                     } else {
                         let msg = "failed to read extended entry identifier";
                         return Err(annotate(error, msg));
@@ -223,9 +541,7 @@ impl Header {
                 id_buffer.pop();
             }
             identifier = id_buffer;
-            if identifier == BSD_SYMBOL_LOOKUP_TABLE_ID
-                || identifier == BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID
-            {
+            if is_bsd_symbol_table_id(&identifier) {
                 io::copy(&mut reader.by_ref().take(size), &mut io::sink())?;
                 return Ok(Some((Header::new(identifier, size), header_len)));
             }
@@ -236,10 +552,83 @@ impl Header {
         )))
     }
 
-    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+    /// Returns an error if any of the numeric fields (with `size` as the
+    /// value actually stored in the file size field) is too large for its
+    /// fixed-width field in the entry header.
+    fn check_fields(&self, size: u64) -> io::Result<()> {
+        check_field("mtime", self.mtime.to_string(), 12)?;
+        check_field("owner ID", self.uid.to_string(), 6)?;
+        check_field("group ID", self.gid.to_string(), 6)?;
+        check_field("file mode", format!("{:o}", self.mode), 8)?;
+        check_field("file size", size.to_string(), 10)
+    }
+
+    /// Reads an AIX big archive member header, along with the identifier
+    /// that follows it.  Returns the header, the length of the header and
+    /// identifier together, and the offset of the next member's header.
+    pub(crate) fn read_aix<R: Read>(
+        reader: &mut R,
+        check_terminator: bool,
+        lenient: bool,
+    ) -> io::Result<(Header, u64, u64)> {
+        let mut buffer = [0; AIX_ENTRY_HEADER_LEN];
+        reader.read_exact(&mut buffer).map_err(|error| {
+            if error.kind() == ErrorKind::UnexpectedEof {
+                ArError::TruncatedHeader.into()
+            } else {
+                annotate(error, "failed to read archive entry header")
+            }
+        })?;
+        let size = parse_number("file size", &buffer[0..20], 10, lenient)?;
+        let next_member =
+            parse_number("next member offset", &buffer[20..40], 10, lenient)?;
+        let mtime = parse_number_permitting_minus_one(
+            "timestamp",
+            &buffer[60..72],
+            10,
+            lenient,
+        )?;
+        let uid = parse_number_permitting_empty(
+            "owner ID",
+            &buffer[72..84],
+            10,
+            lenient,
+        )? as u32;
+        let gid = parse_number_permitting_empty(
+            "group ID",
+            &buffer[84..96],
+            10,
+            lenient,
+        )? as u32;
+        let mode =
+            parse_number("file mode", &buffer[96..108], 8, lenient)? as u32;
+        let name_length =
+            parse_number("name length", &buffer[108..112], 10, lenient)?;
+        // The name is padded to an even length, and followed by "`\n".
+        let padded_length = name_length + name_length % 2;
+        let mut identifier = vec![0; padded_length as usize + 2];
+        reader.read_exact(&mut identifier).map_err(|error| {
+            if error.kind() == ErrorKind::UnexpectedEof {
+                ArError::TruncatedIdentifier.into()
+            } else {
+                annotate(error, "failed to read extended entry identifier")
+            }
+        })?;
+        let terminator = identifier.split_off(padded_length as usize);
+        if check_terminator && terminator != b"`\n" {
+            return Err(invalid_field("terminator", &terminator));
+        }
+        identifier.truncate(name_length as usize);
+        let header_len = AIX_ENTRY_HEADER_LEN as u64 + padded_length + 2;
+        let header = Header { identifier, mtime, uid, gid, mode, size };
+        Ok((header, header_len, next_member))
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         if self.identifier.len() > 16 || self.identifier.contains(&b' ') {
             let padding_length = (4 - self.identifier.len() % 4) % 4;
             let padded_length = self.identifier.len() + padding_length;
+            self.check_fields(self.size + padded_length as u64)?;
             writeln!(
                 writer,
                 "#1/{:<13}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
@@ -253,6 +642,7 @@ impl Header {
             writer.write_all(&self.identifier)?;
             writer.write_all(&vec![0; padding_length])?;
         } else {
+            self.check_fields(self.size)?;
             writer.write_all(&self.identifier)?;
             writer.write_all(&vec![b' '; 16 - self.identifier.len()])?;
             writeln!(
@@ -268,12 +658,12 @@ impl Header {
         &self,
         writer: &mut W,
         names: &HashMap<Vec<u8>, usize>,
-    ) -> Result<()>
+    ) -> io::Result<()>
     where
         W: Write,
     {
-        if self.identifier.len() > 15 {
-            let offset = names[&self.identifier];
+        self.check_fields(self.size)?;
+        if let Some(offset) = names.get(&self.identifier) {
             write!(writer, "/{:<15}", offset)?;
         } else {
             writer.write_all(&self.identifier)?;
@@ -289,18 +679,233 @@ impl Header {
     }
 }
 
-fn parse_number(field_name: &str, bytes: &[u8], radix: u32) -> Result<u64> {
-    if let Ok(string) = str::from_utf8(bytes) {
-        if let Ok(value) = u64::from_str_radix(string.trim_end(), radix) {
+impl fmt::Debug for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Header")
+            .field("identifier", &String::from_utf8_lossy(&self.identifier))
+            .field("mtime", &self.mtime)
+            .field("uid", &self.uid)
+            .field("gid", &self.gid)
+            .field("mode", &format_args!("{:#o}", self.mode))
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+struct DisplayLong<'a>(&'a Header);
+
+impl<'a> fmt::Display for DisplayLong<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep",
+            "Oct", "Nov", "Dec",
+        ];
+        let header = self.0;
+        let seconds = header.mtime % 86400;
+        let (year, month, day) = civil_from_days(header.mtime / 86400);
+        write!(
+            f,
+            "{} {}/{} {:>8} {} {:>2} {:02}:{:02} {} {}",
+            permission_string(header.mode),
+            header.uid,
+            header.gid,
+            header.size,
+            MONTHS[month as usize - 1],
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            year,
+            header.identifier_str()
+        )
+    }
+}
+
+/// Formats the permission bits of a mode like `ls -l` does, ignoring the
+/// file type bits.
+fn permission_string(mode: u32) -> String {
+    let mut string = String::with_capacity(9);
+    for (shift, special, special_char) in
+        [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')]
+    {
+        let bits = mode >> shift;
+        string.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        string.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        string.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    string
+}
+
+/// Converts a number of days since the Unix epoch into a (year, month, day)
+/// date in the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so that leap days fall at the end of
+    // each 400-year era.
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month =
+        if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// A builder for an archive entry header, created by `Header::builder()`.
+///
+/// Any field that isn't set is zero, as for `Header::new()`.
+#[derive(Clone, Debug)]
+pub struct HeaderBuilder {
+    header: Header,
+}
+
+impl HeaderBuilder {
+    /// Sets the last modification time in Unix time format.
+    pub fn mtime(mut self, mtime: u64) -> HeaderBuilder {
+        self.header.mtime = mtime;
+        self
+    }
+
+    /// Sets the value of the owner's user ID field.
+    pub fn uid(mut self, uid: u32) -> HeaderBuilder {
+        self.header.uid = uid;
+        self
+    }
+
+    /// Sets the value of the group's user ID field.
+    pub fn gid(mut self, gid: u32) -> HeaderBuilder {
+        self.header.gid = gid;
+        self
+    }
+
+    /// Sets the mode bits for this file.
+    pub fn mode(mut self, mode: u32) -> HeaderBuilder {
+        self.header.mode = mode;
+        self
+    }
+
+    /// Returns the header, or an error carrying `ar::Error::FieldOverflow` if
+    /// any of its numeric fields is too large to be written to an archive.
+    pub fn build(self) -> Result<Header> {
+        self.header.check_fields(self.header.size)?;
+        Ok(self.header)
+    }
+}
+
+/// Reads the rest of an AIX big archive's fixed-length header, after the
+/// magic string, and returns the offsets of the first and last members' headers
+/// (both zero if there are no members).
+pub(crate) fn read_aix_fixed_header<R: Read>(
+    reader: &mut R,
+    lenient: bool,
+) -> io::Result<(u64, u64)> {
+    let mut buffer = [0; AIX_FIXED_HEADER_LEN as usize - 8];
+    reader
+        .read_exact(&mut buffer)
+        .map_err(|error| annotate(error, "failed to read global header"))?;
+    // The offsets of the member table, the 32- and 64-bit global symbol
+    // tables, the first and last members, and the free list, in that order.
+    let first_member =
+        parse_number("first member offset", &buffer[60..80], 10, lenient)?;
+    let last_member =
+        parse_number("last member offset", &buffer[80..100], 10, lenient)?;
+    Ok((first_member, last_member))
+}
+
+/// Returns the file name component of `path`, as an entry identifier.
+pub(crate) fn file_name_identifier(path: &Path) -> io::Result<Vec<u8>> {
+    let name = path.file_name().ok_or_else(|| {
+        let msg = "Given path doesn't have a file name";
+        Error::new(ErrorKind::InvalidInput, msg)
+    })?;
+    osstr_to_bytes(name)
+}
+
+#[cfg(unix)]
+pub(crate) fn osstr_to_bytes(string: &OsStr) -> io::Result<Vec<u8>> {
+    Ok(string.as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn osstr_to_bytes(string: &OsStr) -> io::Result<Vec<u8>> {
+    let utf8: &str = string.to_str().ok_or_else(|| {
+        Error::new(ErrorKind::InvalidData, "Non-UTF8 file name")
+    })?;
+    Ok(utf8.as_bytes().to_vec())
+}
+
+pub(crate) fn is_gnu_symbol_table_id(identifier: &[u8]) -> bool {
+    identifier == GNU_SYMBOL_LOOKUP_TABLE_ID
+        || identifier == GNU_SYMBOL_LOOKUP_TABLE_64_ID
+}
+
+pub(crate) fn is_bsd_symbol_table_id(identifier: &[u8]) -> bool {
+    identifier == BSD_SYMBOL_LOOKUP_TABLE_ID
+        || identifier == BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID
+}
+
+/// Returns the name starting at the given offset in a GNU name table.
+pub(crate) fn gnu_table_name(
+    name_table: &[u8],
+    start: usize,
+) -> io::Result<&[u8]> {
+    if start > name_table.len() {
+        let error = ArError::NameNotInGnuTable { index: start };
+        return Err(error.into());
+    }
+    // Names in the table end with "/\n" (or a NUL byte), so that thin archive
+    // paths may themselves contain slashes.
+    let names = &name_table[start..];
+    let end = (0..names.len())
+        .position(|i| {
+            names[i] == b'\x00'
+                || (names[i] == b'/'
+                    && names.get(i + 1).is_none_or(|&ch| ch == b'\n'))
+        })
+        .unwrap_or(names.len());
+    Ok(&names[..end])
+}
+
+fn check_field(
+    field_name: &'static str,
+    digits: String,
+    width: usize,
+) -> io::Result<()> {
+    if digits.len() > width {
+        let error =
+            ArError::FieldOverflow { field: field_name, value: digits, width };
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+fn invalid_field(field_name: &'static str, bytes: &[u8]) -> Error {
+    let value = String::from_utf8_lossy(bytes).into_owned();
+    ArError::InvalidField { field: field_name, value }.into()
+}
+
+fn parse_number(
+    field_name: &'static str,
+    bytes: &[u8],
+    radix: u32,
+    lenient: bool,
+) -> io::Result<u64> {
+    if let Some(digits) = field_digits(bytes, radix, lenient) {
+        if let Ok(value) = u64::from_str_radix(digits, radix) {
             return Ok(value);
         }
     }
-    let msg = format!(
-        "Invalid {} field in entry header ({:?})",
-        field_name,
-        String::from_utf8_lossy(bytes)
-    );
-    Err(Error::new(ErrorKind::InvalidData, msg))
+    Err(invalid_field(field_name, bytes))
 }
 
 /*
@@ -308,24 +913,15 @@ fn parse_number(field_name: &str, bytes: &[u8], radix: u32) -> Result<u64> {
  * as MS tools may emit for mtime.
  */
 fn parse_number_permitting_minus_one(
-    field_name: &str,
+    field_name: &'static str,
     bytes: &[u8],
     radix: u32,
-) -> Result<u64> {
-    if let Ok(string) = str::from_utf8(bytes) {
-        let trimmed = string.trim_end();
-        if trimmed == "-1" {
-            return Ok(0);
-        } else if let Ok(value) = u64::from_str_radix(trimmed, radix) {
-            return Ok(value);
-        }
+    lenient: bool,
+) -> io::Result<u64> {
+    if trim_field(bytes, lenient) == b"-1" {
+        return Ok(0);
     }
-    let msg = format!(
-        "Invalid {} field in entry header ({:?})",
-        field_name,
-        String::from_utf8_lossy(bytes)
-    );
-    Err(Error::new(ErrorKind::InvalidData, msg))
+    parse_number(field_name, bytes, radix, lenient)
 }
 
 /*
@@ -333,22 +929,46 @@ fn parse_number_permitting_minus_one(
  * all spaces (eg all 0x20) as MS tools emit for UID/GID
  */
 fn parse_number_permitting_empty(
-    field_name: &str,
+    field_name: &'static str,
     bytes: &[u8],
     radix: u32,
-) -> Result<u64> {
-    if let Ok(string) = str::from_utf8(bytes) {
-        let trimmed = string.trim_end();
-        if trimmed.is_empty() {
-            return Ok(0);
-        } else if let Ok(value) = u64::from_str_radix(trimmed, radix) {
-            return Ok(value);
+    lenient: bool,
+) -> io::Result<u64> {
+    if trim_field(bytes, lenient).is_empty() {
+        return Ok(0);
+    }
+    parse_number(field_name, bytes, radix, lenient)
+}
+
+/*
+ * Returns the digits of a numeric header field.  Normally the field must be
+ * nothing but the digits followed by space padding; in lenient mode, leading
+ * whitespace and NUL bytes are skipped, and anything after the leading digits
+ * is ignored.
+ */
+fn field_digits(bytes: &[u8], radix: u32, lenient: bool) -> Option<&str> {
+    if lenient {
+        let bytes = trim_field(bytes, true);
+        let len = bytes
+            .iter()
+            .position(|&byte| !(byte as char).is_digit(radix))
+            .unwrap_or(bytes.len());
+        str::from_utf8(&bytes[..len]).ok()
+    } else {
+        str::from_utf8(bytes).ok().map(str::trim_end)
+    }
+}
+
+fn trim_field(bytes: &[u8], lenient: bool) -> &[u8] {
+    if lenient {
+        let is_padding = |byte: &u8| byte.is_ascii_whitespace() || *byte == 0;
+        let start = bytes.iter().position(|byte| !is_padding(byte));
+        let end = bytes.iter().rposition(|byte| !is_padding(byte));
+        match (start, end) {
+            (Some(start), Some(end)) => &bytes[start..=end],
+            _ => &[],
         }
+    } else {
+        bytes.trim_ascii_end()
     }
-    let msg = format!(
-        "Invalid {} field in entry header ({:?})",
-        field_name,
-        String::from_utf8_lossy(bytes)
-    );
-    Err(Error::new(ErrorKind::InvalidData, msg))
 }
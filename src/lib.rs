@@ -24,7 +24,15 @@
 //!   stores filenames in a slightly different, incompatible way, and has its
 //!   own strategy for supporting long filenames.
 //!
-//! This crate supports reading and writing all three of these variants.
+//! This crate supports reading and writing all three of these variants.  It
+//! can also read (but not write) the *AIX big archive* format used by IBM
+//! AIX, which has an altogether different header layout.
+//!
+//! # Optional features
+//!
+//! * `deb`: adds `Archive::deb_member()`, which finds the well-known members
+//!   of a Debian package file (see `DebMember`), so that they needn't be
+//!   looked up by name.
 //!
 //! # Example usage
 //!
@@ -66,15 +74,23 @@
 
 #![warn(missing_docs)]
 
-pub use crate::archive::{Archive, Variant};
+pub use crate::archive::{
+    Archive, Variant, AIX_BIG_MAGIC, COMMON_MAGIC, THIN_MAGIC,
+};
+pub use crate::builder::convert;
 pub use crate::builder::Builder;
 pub use crate::builder::GnuBuilder;
-pub use crate::entry::Entry;
-pub use crate::header::Header;
-pub use crate::symbols::Symbols;
+#[cfg(feature = "deb")]
+pub use crate::deb::DebMember;
+pub use crate::entry::{Entry, HashedEntry};
+pub use crate::error::{Error, Result};
+pub use crate::header::{Header, HeaderBuilder, ParsedKind};
+pub use crate::symbols::{SymbolTableKind, Symbols};
 
 mod archive;
 mod builder;
+#[cfg(feature = "deb")]
+mod deb;
 mod entry;
 mod error;
 mod header;
@@ -1,35 +1,179 @@
-use std::io::Read;
+use std::io::{Error, ErrorKind, Read};
 
+use crate::archive::{
+    BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID, BSD_SYMBOL_LOOKUP_TABLE_ID,
+    GNU_SYMBOL_LOOKUP_TABLE_64_ID, GNU_SYMBOL_LOOKUP_TABLE_ID,
+};
+use crate::error::Result;
 use crate::Archive;
 
+/// The kind of symbol table an archive has, as reported by
+/// `Symbols::kind()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SymbolTableKind {
+    /// A GNU `/` symbol table whose symbols are in lexical order, so that a
+    /// linker could binary search it.
+    GnuSorted,
+    /// A GNU `/` symbol table whose symbols aren't in lexical order, as GNU
+    /// `ar` writes them (in the order of the members defining them).
+    GnuUnsorted,
+    /// A GNU `/SYM64/` symbol table, with 64-bit member offsets.
+    Gnu64,
+    /// A BSD `__.SYMDEF SORTED` symbol table.
+    BsdSorted,
+    /// A BSD `__.SYMDEF` symbol table.
+    BsdUnsorted,
+    /// The archive has no symbol table.
+    None,
+}
+
 /// An iterator over the symbols in the symbol table of an archive.
+///
+/// Each item is a symbol name together with the byte offset (from the start
+/// of the archive) of the header of the archive member that defines it.  The
+/// names are borrowed from the string table that was read from the archive,
+/// so iterating doesn't allocate.
 pub struct Symbols<'a, R: 'a + Read> {
     pub(crate) archive: &'a Archive<R>,
     pub(crate) index: usize,
 }
 
-impl<'a, R: Read> Iterator for Symbols<'a, R> {
-    type Item = &'a [u8];
+impl<'a, R: Read> Symbols<'a, R> {
+    /// Returns the number of symbols in the whole symbol table, regardless of
+    /// how far this iterator has advanced (unlike `len()`, which counts the
+    /// symbols that it has yet to produce).
+    pub fn table_len(&self) -> usize {
+        self.archive.symbol_table.as_ref().map_or(0, SymbolTable::len)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(ref table) = self.archive.symbol_table {
-            if self.index < table.len() {
-                let next = table[self.index].0.as_slice();
-                self.index += 1;
-                return Some(next);
+    /// Returns which kind of symbol table the archive has.  A BSD table is
+    /// reported as sorted if it is named `__.SYMDEF SORTED`, whereas a GNU
+    /// `/` table is reported as sorted if its symbols turn out to be in
+    /// lexical order (byte by byte), since the format has no way to say so.
+    pub fn kind(&self) -> SymbolTableKind {
+        let header = match self.archive.symbol_table_header {
+            Some(ref location) => &location.header,
+            None => return SymbolTableKind::None,
+        };
+        let sorted = self
+            .archive
+            .symbol_table
+            .as_ref()
+            .is_some_and(|table| table.sorted);
+        match header.identifier() {
+            GNU_SYMBOL_LOOKUP_TABLE_ID if sorted => SymbolTableKind::GnuSorted,
+            GNU_SYMBOL_LOOKUP_TABLE_ID => SymbolTableKind::GnuUnsorted,
+            GNU_SYMBOL_LOOKUP_TABLE_64_ID => SymbolTableKind::Gnu64,
+            BSD_SORTED_SYMBOL_LOOKUP_TABLE_ID => SymbolTableKind::BsdSorted,
+            BSD_SYMBOL_LOOKUP_TABLE_ID => SymbolTableKind::BsdUnsorted,
+            _ => SymbolTableKind::None,
+        }
+    }
+
+    /// Returns the offset of the header of the archive member that defines
+    /// the given symbol, or `None` if the symbol table doesn't contain it.
+    /// This searches the whole symbol table, regardless of how far this
+    /// iterator has advanced.  If the symbol is listed more than once, the
+    /// first member listed is returned, as a linker would choose.
+    pub fn lookup(&self, name: &[u8]) -> Option<u64> {
+        let table = self.archive.symbol_table.as_ref()?;
+        table
+            .iter()
+            .find(|&(symbol, _)| symbol == name)
+            .map(|(_, offset)| offset)
+    }
+
+    /// Checks that every symbol in the symbol table refers to the header of
+    /// a member of the archive, as a linker relies on.  Returns an error for
+    /// the first symbol found that doesn't, such as one left behind in a
+    /// stale table after the archive's members were changed.
+    pub fn validate(&self) -> Result<()> {
+        let table = match self.archive.symbol_table {
+            Some(ref table) => table,
+            None => return Ok(()),
+        };
+        for (symbol, offset) in table.iter() {
+            if self.archive.member_index_at(offset).is_none() {
+                let msg = format!(
+                    "Symbol {:?} refers to a member at offset {}, but there \
+                     is no member there",
+                    String::from_utf8_lossy(symbol),
+                    offset
+                );
+                return Err(Error::new(ErrorKind::InvalidData, msg).into());
             }
         }
-        None
+        Ok(())
+    }
+}
+
+impl<'a, R: Read> Iterator for Symbols<'a, R> {
+    type Item = (&'a [u8], u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.archive.symbol_table.as_ref()?.get(self.index)?;
+        self.index += 1;
+        Some(next)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = if let Some(ref table) = self.archive.symbol_table {
-            table.len() - self.index
-        } else {
-            0
-        };
+        let remaining = self.table_len() - self.index;
         (remaining, Some(remaining))
     }
 }
 
 impl<'a, R: Read> ExactSizeIterator for Symbols<'a, R> {}
+
+/// The symbol table of an archive, as read from the archive: the string
+/// table holding every symbol name, and where in it each symbol's name is,
+/// along with the offset of the member defining the symbol.
+pub(crate) struct SymbolTable {
+    strings: Vec<u8>,
+    symbols: Vec<(usize, usize, u64)>,
+    sorted: bool, // True if the symbol names are in lexical order.
+}
+
+impl SymbolTable {
+    /// Creates a symbol table from a string table of NUL-terminated names,
+    /// and the offset into it of each symbol's name, paired with the offset
+    /// of the member that defines the symbol.  A name runs up to the next NUL
+    /// byte, or to the end of the string table if there is none; a name
+    /// starting beyond the end of the string table is empty.
+    pub(crate) fn new(
+        strings: Vec<u8>,
+        symbols: Vec<(usize, u64)>,
+    ) -> SymbolTable {
+        let symbols = symbols
+            .into_iter()
+            .map(|(start, offset)| {
+                let start = start.min(strings.len());
+                let end = strings[start..]
+                    .iter()
+                    .position(|&byte| byte == 0)
+                    .map_or(strings.len(), |len| start + len);
+                (start, end, offset)
+            })
+            .collect();
+        let mut table = SymbolTable { strings, symbols, sorted: false };
+        table.sorted = (1..table.len()).all(|index| {
+            table.get(index - 1).map(|(name, _)| name)
+                <= table.get(index).map(|(name, _)| name)
+        });
+        table
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<(&[u8], u64)> {
+        let &(start, end, offset) = self.symbols.get(index)?;
+        Some((&self.strings[start..end], offset))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], u64)> {
+        self.symbols.iter().map(move |&(start, end, offset)| {
+            (&self.strings[start..end], offset)
+        })
+    }
+}